@@ -0,0 +1,745 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::instruction::Instruction;
+
+// ROMs are loaded at 0x200
+const PROGRAM_START: u16 = 0x200;
+
+/// An error found while assembling, pointing at the line and column it was found at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+/// Where a statement or operand came from
+#[derive(Clone, Debug)]
+struct Location {
+    file: String,
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    fn error(&self, message: String) -> AssemblyError {
+        AssemblyError {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+            message,
+        }
+    }
+
+    fn at_column(&self, column: usize) -> Location {
+        Location { file: self.file.clone(), line: self.line, column }
+    }
+}
+
+/// A number, label or constant, optionally added to or subtracted from others
+#[derive(Clone, Debug)]
+struct Expression {
+    terms: Vec<(i64, Term)>,
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+/// A parsed instruction operand
+#[derive(Clone, Debug)]
+enum Operand {
+    Register(u8),
+    RegisterRange(u8, u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    HighFont,
+    Bcd,
+    Flags,
+    Long(Expression),
+    Value(Expression),
+}
+
+#[derive(Clone, Debug)]
+enum StatementKind {
+    Instruction { mnemonic: String, operands: Vec<(Operand, Location)> },
+    Bytes(Vec<(Expression, Location)>),
+    Words(Vec<(Expression, Location)>),
+}
+
+#[derive(Clone, Debug)]
+struct Statement {
+    location: Location,
+    kind: StatementKind,
+}
+
+/// Two pass CHIP-8 assembler.
+/// It accepts the Cowgod-style mnemonics used by the interpreter (CLS, RET, JP, CALL, SE, LD, DRW, ...),
+///     labels, constants, db/dw data directives and includes.
+pub struct Assembler {
+    statements: Vec<Statement>,
+    symbols: HashMap<String, i64>,
+    address: usize,
+    include_stack: Vec<PathBuf>,
+}
+
+impl Assembler {
+    /// Assemble a source file into a ROM. Included files are resolved relative to the including file.
+    pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AssemblyError> {
+        let mut assembler: Assembler = Assembler::new();
+        let location: Location = Location { file: path.display().to_string(), line: 0, column: 0 };
+        assembler.include(path, &location)?;
+        assembler.emit()
+    }
+
+    /// Assemble source code into a ROM. Included files are resolved relative to the working directory.
+    pub fn assemble_source(source: &str, name: &str) -> Result<Vec<u8>, AssemblyError> {
+        let mut assembler: Assembler = Assembler::new();
+        assembler.parse_source(source, name, Path::new("."))?;
+        assembler.emit()
+    }
+
+    fn new() -> Self {
+        Assembler {
+            statements: Vec::new(),
+            symbols: HashMap::new(),
+            address: PROGRAM_START as usize,
+            include_stack: Vec::new(),
+        }
+    }
+
+    /// Parse a file and add its statements
+    fn include(&mut self, path: &Path, location: &Location) -> Result<(), AssemblyError> {
+        let canonical: PathBuf = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.include_stack.contains(&canonical) {
+            return Err(location.error(format!("{} includes itself", path.display())));
+        }
+
+        let source: String = std::fs::read_to_string(path)
+            .map_err(|e| location.error(format!("Could not read {}: {}", path.display(), e)))?;
+        let directory: PathBuf = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        self.include_stack.push(canonical);
+        let result = self.parse_source(&source, &path.display().to_string(), &directory);
+        self.include_stack.pop();
+        result
+    }
+
+    /// First pass: parse every line, assign addresses and define labels and constants
+    fn parse_source(&mut self, source: &str, file: &str, directory: &Path) -> Result<(), AssemblyError> {
+        for (index, raw_line) in source.lines().enumerate() {
+            let line: &str = strip_comment(raw_line);
+            let mut location: Location = Location { file: file.to_string(), line: index + 1, column: 1 };
+            let mut rest: &str = line;
+
+            // Any number of labels can come before a statement
+            loop {
+                let trimmed: &str = rest.trim_start();
+                location.column = column_of(line, trimmed);
+                let name_end: usize = trimmed
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                    .unwrap_or(trimmed.len());
+                if name_end == 0 || !trimmed[name_end..].starts_with(':') {
+                    break;
+                }
+                self.define(&trimmed[..name_end], self.address as i64, &location)?;
+                rest = &trimmed[name_end + 1..];
+            }
+
+            let statement: &str = rest.trim();
+            if statement.is_empty() {
+                continue;
+            }
+            location.column = column_of(line, statement);
+
+            let (keyword, arguments) = split_keyword(statement);
+            let arguments_column: usize = column_of(line, arguments);
+
+            // Constants: NAME = value or NAME EQU value
+            let (second, value) = split_keyword(arguments);
+            if second == "=" || second.eq_ignore_ascii_case("equ") {
+                let value_location: Location = location.at_column(column_of(line, value));
+                let expression: Expression = parse_expression(value, &value_location)?;
+                let constant: i64 = self.evaluate(&expression, &value_location)?;
+                self.define(keyword, constant, &location)?;
+                continue;
+            }
+
+            match keyword.to_ascii_lowercase().as_str() {
+                "include" => {
+                    let file_name: &str = arguments.trim().trim_matches('"');
+                    if file_name.is_empty() {
+                        return Err(location.error(String::from("include needs a file name")));
+                    }
+                    self.include(&directory.join(file_name), &location)?;
+                }
+                "db" => {
+                    let values = parse_data(arguments, line, &location)?;
+                    let size: usize = values.len();
+                    self.push(location, StatementKind::Bytes(values), size)?;
+                }
+                "dw" => {
+                    let values = parse_data(arguments, line, &location)?;
+                    let size: usize = values.len() * 2;
+                    self.push(location, StatementKind::Words(values), size)?;
+                }
+                _ => {
+                    let mut operands: Vec<(Operand, Location)> = Vec::new();
+                    if !arguments.trim().is_empty() {
+                        for (text, column) in split_operands(arguments, arguments_column) {
+                            let operand_location: Location = location.at_column(column);
+                            operands.push((parse_operand(text, &operand_location)?, operand_location));
+                        }
+                    }
+                    let size: usize = if operands.iter().any(|(o, _)| matches!(o, Operand::Long(_))) { 4 } else { 2 };
+                    let mnemonic: String = keyword.to_ascii_uppercase();
+                    self.push(location, StatementKind::Instruction { mnemonic, operands }, size)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, location: Location, kind: StatementKind, size: usize) -> Result<(), AssemblyError> {
+        if self.address + size > 0x10000 {
+            return Err(location.error(String::from("Program does not fit in memory")));
+        }
+        self.statements.push(Statement { location, kind });
+        self.address += size;
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, value: i64, location: &Location) -> Result<(), AssemblyError> {
+        if !is_symbol_name(name) || parse_register(name).is_some() {
+            return Err(location.error(format!("Invalid label or constant name: {}", name)));
+        }
+        if self.symbols.insert(name.to_string(), value).is_some() {
+            return Err(location.error(format!("{} is already defined", name)));
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self, expression: &Expression, location: &Location) -> Result<i64, AssemblyError> {
+        let mut total: i64 = 0;
+        for (sign, term) in &expression.terms {
+            let value: i64 = match term {
+                Term::Number(number) => *number,
+                Term::Symbol(name) => *self.symbols
+                    .get(name)
+                    .ok_or_else(|| location.error(format!("Undefined label or constant: {}", name)))?,
+            };
+            total += sign * value;
+        }
+        Ok(total)
+    }
+
+    /// Second pass: resolve every expression and encode the statements
+    fn emit(&self) -> Result<Vec<u8>, AssemblyError> {
+        let mut rom: Vec<u8> = Vec::new();
+        for statement in &self.statements {
+            match &statement.kind {
+                StatementKind::Bytes(values) => {
+                    for (expression, location) in values {
+                        rom.push(self.resolve(expression, location, -0x80, 0xFF)? as u8);
+                    }
+                }
+                StatementKind::Words(values) => {
+                    for (expression, location) in values {
+                        let word: u16 = self.resolve(expression, location, -0x8000, 0xFFFF)? as u16;
+                        rom.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+                StatementKind::Instruction { mnemonic, operands } => {
+                    let instruction: Instruction = self.build(mnemonic, operands, &statement.location)?;
+                    for word in instruction.encode() {
+                        rom.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+            }
+        }
+        Ok(rom)
+    }
+
+    /// Resolve an expression and check that it fits in the given range
+    fn resolve(&self, expression: &Expression, location: &Location, min: i64, max: i64) -> Result<i64, AssemblyError> {
+        let value: i64 = self.evaluate(expression, location)?;
+        if value < min || value > max {
+            return Err(location.error(format!("Value {} is out of range ({} to {})", value, min, max)));
+        }
+        // Negative values are stored as two's complement
+        Ok(if value < 0 { value + max + 1 } else { value })
+    }
+
+    /// Turn a mnemonic and its operands into an instruction
+    fn build(&self, mnemonic: &str, operands: &[(Operand, Location)], location: &Location) -> Result<Instruction, AssemblyError> {
+        use Operand::*;
+
+        let address = |index: usize| -> Result<u16, AssemblyError> {
+            match &operands[index] {
+                (Value(expression), at) => Ok(self.resolve(expression, at, 0, 0xFFF)? as u16),
+                (_, at) => Err(at.error(String::from("Expected an address"))),
+            }
+        };
+        let byte = |index: usize| -> Result<u8, AssemblyError> {
+            match &operands[index] {
+                (Value(expression), at) => Ok(self.resolve(expression, at, -0x80, 0xFF)? as u8),
+                (_, at) => Err(at.error(String::from("Expected a byte"))),
+            }
+        };
+        let nibble = |index: usize| -> Result<u8, AssemblyError> {
+            match &operands[index] {
+                (Value(expression), at) => Ok(self.resolve(expression, at, 0, 0xF)? as u8),
+                (_, at) => Err(at.error(String::from("Expected a value from 0 to 15"))),
+            }
+        };
+
+        let kinds: Vec<&Operand> = operands.iter().map(|(operand, _)| operand).collect();
+        let instruction: Instruction = match (mnemonic, kinds.as_slice()) {
+            ("CLS", []) => Instruction::Cls,
+            ("RET", []) => Instruction::Ret,
+            ("SCR", []) => Instruction::Scr,
+            ("SCL", []) => Instruction::Scl,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::Low,
+            ("HIGH", []) => Instruction::High,
+            ("AUDIO", []) => Instruction::Audio,
+            ("SCD", [Value(_)]) => Instruction::Scd(nibble(0)?),
+            ("SYS", [Value(_)]) => Instruction::Sys(address(0)?),
+            ("JP", [Value(_)]) => Instruction::Jp(address(0)?),
+            ("JP", [Register(0), Value(_)]) => Instruction::JpV0(address(1)?),
+            ("CALL", [Value(_)]) => Instruction::Call(address(0)?),
+            ("SE", [Register(x), Register(y)]) => Instruction::SeRegister(*x, *y),
+            ("SE", [Register(x), Value(_)]) => Instruction::SeByte(*x, byte(1)?),
+            ("SNE", [Register(x), Register(y)]) => Instruction::SneRegister(*x, *y),
+            ("SNE", [Register(x), Value(_)]) => Instruction::SneByte(*x, byte(1)?),
+            ("LD", [Register(x), Register(y)]) => Instruction::LdRegister(*x, *y),
+            ("LD", [Register(x), Value(_)]) => Instruction::LdByte(*x, byte(1)?),
+            ("LD", [I, Value(_)]) => Instruction::LdI(address(1)?),
+            ("LD", [I, Long(expression)]) => {
+                Instruction::LdILong(self.resolve(expression, &operands[1].1, 0, 0xFFFF)? as u16)
+            }
+            ("LD", [Register(x), DelayTimer]) => Instruction::LdVxDt(*x),
+            ("LD", [Register(x), Key]) => Instruction::LdVxK(*x),
+            ("LD", [DelayTimer, Register(x)]) => Instruction::LdDtVx(*x),
+            ("LD", [SoundTimer, Register(x)]) => Instruction::LdStVx(*x),
+            ("LD", [Font, Register(x)]) => Instruction::LdF(*x),
+            ("LD", [HighFont, Register(x)]) => Instruction::LdHf(*x),
+            ("LD", [Bcd, Register(x)]) => Instruction::LdB(*x),
+            ("LD", [IndirectI, Register(x)]) => Instruction::Save(*x),
+            ("LD", [Register(x), IndirectI]) => Instruction::Load(*x),
+            ("LD", [IndirectI, RegisterRange(x, y)]) => Instruction::SaveRange(*x, *y),
+            ("LD", [RegisterRange(x, y), IndirectI]) => Instruction::LoadRange(*x, *y),
+            ("LD", [Flags, Register(x)]) => Instruction::SaveFlags(*x),
+            ("LD", [Register(x), Flags]) => Instruction::LoadFlags(*x),
+            ("ADD", [Register(x), Register(y)]) => Instruction::AddRegister(*x, *y),
+            ("ADD", [Register(x), Value(_)]) => Instruction::AddByte(*x, byte(1)?),
+            ("ADD", [I, Register(x)]) => Instruction::AddI(*x),
+            ("OR", [Register(x), Register(y)]) => Instruction::Or(*x, *y),
+            ("AND", [Register(x), Register(y)]) => Instruction::And(*x, *y),
+            ("XOR", [Register(x), Register(y)]) => Instruction::Xor(*x, *y),
+            ("SUB", [Register(x), Register(y)]) => Instruction::Sub(*x, *y),
+            ("SUBN", [Register(x), Register(y)]) => Instruction::Subn(*x, *y),
+            // Without Vy the register is shifted in place under every quirk setting
+            ("SHR", [Register(x)]) => Instruction::Shr(*x, *x),
+            ("SHR", [Register(x), Register(y)]) => Instruction::Shr(*x, *y),
+            ("SHL", [Register(x)]) => Instruction::Shl(*x, *x),
+            ("SHL", [Register(x), Register(y)]) => Instruction::Shl(*x, *y),
+            ("RND", [Register(x), Value(_)]) => Instruction::Rnd(*x, byte(1)?),
+            ("DRW", [Register(x), Register(y), Value(_)]) => Instruction::Drw(*x, *y, nibble(2)?),
+            ("SKP", [Register(x)]) => Instruction::Skp(*x),
+            ("SKNP", [Register(x)]) => Instruction::Sknp(*x),
+            ("PLANE", [Value(expression)]) => Instruction::Plane(self.resolve(expression, &operands[0].1, 0, 3)? as u8),
+            ("PITCH", [Register(x)]) => Instruction::Pitch(*x),
+            _ => {
+                let is_known: bool = [
+                    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SYS", "JP", "CALL",
+                    "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW",
+                    "SKP", "SKNP", "PLANE", "PITCH",
+                ].contains(&mnemonic);
+                return Err(location.error(if is_known {
+                    format!("Invalid operands for {}", mnemonic)
+                } else {
+                    format!("Unknown instruction: {}", mnemonic)
+                }));
+            }
+        };
+
+        Ok(instruction)
+    }
+}
+
+/// Remove a ; comment, ignoring semicolons inside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut in_string: bool = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// 1-based column of a sub-slice within its line
+fn column_of(line: &str, part: &str) -> usize {
+    (part.as_ptr() as usize).saturating_sub(line.as_ptr() as usize) + 1
+}
+
+/// Split the first word off a statement
+fn split_keyword(text: &str) -> (&str, &str) {
+    let text: &str = text.trim_start();
+    match text.find(|c: char| c.is_whitespace()) {
+        Some(index) => (&text[..index], text[index..].trim_start()),
+        None => (text, &text[text.len()..]),
+    }
+}
+
+/// Split comma separated operands, returning each one with its column
+fn split_operands(text: &str, start_column: usize) -> Vec<(&str, usize)> {
+    let mut operands: Vec<(&str, usize)> = Vec::new();
+    let mut in_string: bool = false;
+    let mut start: usize = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                operands.push((&text[start..index], start));
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    operands.push((&text[start..], start));
+
+    operands
+        .into_iter()
+        .map(|(operand, offset)| {
+            let leading: usize = operand.len() - operand.trim_start().len();
+            (operand.trim(), start_column + offset + leading)
+        })
+        .collect()
+}
+
+fn is_symbol_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit: &str = text.strip_prefix('V').or_else(|| text.strip_prefix('v'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower: String = text.to_ascii_lowercase().replace('_', "");
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn parse_expression(text: &str, location: &Location) -> Result<Expression, AssemblyError> {
+    let mut terms: Vec<(i64, Term)> = Vec::new();
+    let mut sign: i64 = 1;
+    let mut current: String = String::new();
+
+    let finish_term = |current: &mut String, sign: i64, terms: &mut Vec<(i64, Term)>| -> Result<(), AssemblyError> {
+        let term: &str = current.trim();
+        if term.is_empty() {
+            return Err(location.error(format!("Invalid expression: {}", text.trim())));
+        }
+        if let Some(number) = parse_number(term) {
+            terms.push((sign, Term::Number(number)));
+        } else if is_symbol_name(term) {
+            terms.push((sign, Term::Symbol(term.to_string())));
+        } else {
+            return Err(location.error(format!("Invalid value: {}", term)));
+        }
+        current.clear();
+        Ok(())
+    };
+
+    for c in text.chars() {
+        match c {
+            '+' | '-' if !current.trim().is_empty() => {
+                finish_term(&mut current, sign, &mut terms)?;
+                sign = if c == '-' { -1 } else { 1 };
+            }
+            '-' if current.trim().is_empty() => sign = -sign,
+            '+' if current.trim().is_empty() => {}
+            _ => current.push(c),
+        }
+    }
+    finish_term(&mut current, sign, &mut terms)?;
+
+    Ok(Expression { terms })
+}
+
+fn parse_operand(text: &str, location: &Location) -> Result<Operand, AssemblyError> {
+    if text.is_empty() {
+        return Err(location.error(String::from("Missing operand")));
+    }
+
+    if let Some(register) = parse_register(text) {
+        return Ok(Operand::Register(register));
+    }
+
+    let upper: String = text.to_ascii_uppercase();
+    match upper.as_str() {
+        "I" => return Ok(Operand::I),
+        "[I]" => return Ok(Operand::IndirectI),
+        "DT" => return Ok(Operand::DelayTimer),
+        "ST" => return Ok(Operand::SoundTimer),
+        "K" => return Ok(Operand::Key),
+        "F" => return Ok(Operand::Font),
+        "HF" => return Ok(Operand::HighFont),
+        "B" => return Ok(Operand::Bcd),
+        "R" => return Ok(Operand::Flags),
+        _ => {}
+    }
+
+    if let Some((first, last)) = text.split_once('-') {
+        if let (Some(x), Some(y)) = (parse_register(first.trim()), parse_register(last.trim())) {
+            return Ok(Operand::RegisterRange(x, y));
+        }
+    }
+
+    if upper.starts_with("LONG ") {
+        return Ok(Operand::Long(parse_expression(&text[5..], location)?));
+    }
+
+    Ok(Operand::Value(parse_expression(text, location)?))
+}
+
+/// Parse the values of a db or dw directive. Strings are stored one byte per character.
+fn parse_data(arguments: &str, line: &str, location: &Location) -> Result<Vec<(Expression, Location)>, AssemblyError> {
+    if arguments.trim().is_empty() {
+        return Err(location.error(String::from("Data directive needs at least one value")));
+    }
+
+    let mut values: Vec<(Expression, Location)> = Vec::new();
+    for (text, column) in split_operands(arguments, column_of(line, arguments)) {
+        let value_location: Location = location.at_column(column);
+        if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+            for c in text[1..text.len() - 1].chars() {
+                values.push((Expression { terms: vec![(1, Term::Number(c as i64))] }, value_location.clone()));
+            }
+        } else {
+            values.push((parse_expression(text, &value_location)?, value_location));
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(source: &str) -> Vec<u8> {
+        Assembler::assemble_source(source, "test.8o").unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect()
+    }
+
+    fn assembly_error(source: &str) -> AssemblyError {
+        Assembler::assemble_source(source, "test.8o").expect_err("source should not assemble")
+    }
+
+    #[test]
+    fn every_mnemonic_is_encoded() {
+        let cases: [(&str, &[u16]); 56] = [
+            ("CLS", &[0x00E0]),
+            ("RET", &[0x00EE]),
+            ("SCD 5", &[0x00C5]),
+            ("SCR", &[0x00FB]),
+            ("SCL", &[0x00FC]),
+            ("EXIT", &[0x00FD]),
+            ("LOW", &[0x00FE]),
+            ("HIGH", &[0x00FF]),
+            ("SYS 0x123", &[0x0123]),
+            ("JP 0x345", &[0x1345]),
+            ("CALL 0x456", &[0x2456]),
+            ("SE V1, 0x22", &[0x3122]),
+            ("SNE V2, 0x33", &[0x4233]),
+            ("SE V3, V4", &[0x5340]),
+            ("LD [I], V1-V4", &[0x5142]),
+            ("LD V4-V1, [I]", &[0x5413]),
+            ("LD V5, 0x44", &[0x6544]),
+            ("ADD V6, 0x55", &[0x7655]),
+            ("LD V7, V8", &[0x8780]),
+            ("OR V7, V8", &[0x8781]),
+            ("AND V7, V8", &[0x8782]),
+            ("XOR V7, V8", &[0x8783]),
+            ("ADD V7, V8", &[0x8784]),
+            ("SUB V7, V8", &[0x8785]),
+            ("SHR V7, V8", &[0x8786]),
+            ("SHR V7", &[0x8776]),
+            ("SUBN V7, V8", &[0x8787]),
+            ("SHL V7, V8", &[0x878E]),
+            ("SHL V7", &[0x877E]),
+            ("SNE V9, VA", &[0x99A0]),
+            ("LD I, 0x678", &[0xA678]),
+            ("JP V0, 0x789", &[0xB789]),
+            ("RND VB, 0x0F", &[0xCB0F]),
+            ("DRW VC, VD, 0xE", &[0xDCDE]),
+            ("SKP VE", &[0xEE9E]),
+            ("SKNP VF", &[0xEFA1]),
+            ("LD I, long 0xBEEF", &[0xF000, 0xBEEF]),
+            ("PLANE 3", &[0xF301]),
+            ("AUDIO", &[0xF002]),
+            ("LD V1, DT", &[0xF107]),
+            ("LD V2, K", &[0xF20A]),
+            ("LD DT, V3", &[0xF315]),
+            ("LD ST, V4", &[0xF418]),
+            ("ADD I, V5", &[0xF51E]),
+            ("LD F, V6", &[0xF629]),
+            ("LD HF, V7", &[0xF730]),
+            ("LD B, V8", &[0xF833]),
+            ("PITCH V9", &[0xF93A]),
+            ("LD [I], VA", &[0xFA55]),
+            ("LD VB, [I]", &[0xFB65]),
+            ("LD R, VC", &[0xFC75]),
+            ("LD VD, R", &[0xFD85]),
+            // Mnemonics, registers and keywords are case insensitive
+            ("ld va, dt", &[0xFA07]),
+            ("drw v0, v1, 15", &[0xD01F]),
+            // Bytes can be negative and are stored as two's complement
+            ("ADD V0, -1", &[0x70FF]),
+            ("LD V1, 0b1010_0101", &[0x61A5]),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(words(&assemble(source)), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        let rom: Vec<u8> = assemble(
+            "start: JP end\n\
+             \x20   CALL routine\n\
+             routine: RET\n\
+             end:\n\
+             \x20   LD I, long sprite\n\
+             \x20   JP start\n\
+             sprite: db 0xFF"
+        );
+        assert_eq!(words(&rom[..12]), [0x1206, 0x2204, 0x00EE, 0xF000, 0x020C, 0x1200]);
+        assert_eq!(rom[12], 0xFF);
+    }
+
+    #[test]
+    fn constants_and_expressions_are_evaluated() {
+        let rom: Vec<u8> = assemble(
+            "WIDTH = 64\n\
+             HALF EQU WIDTH - 32\n\
+             \x20   LD V0, HALF + 0x10 - 1\n\
+             \x20   LD V1, -WIDTH + 65\n\
+             \x20   JP next + 2\n\
+             next: CLS\n\
+             \x20   CLS"
+        );
+        assert_eq!(words(&rom), [0x602F, 0x6101, 0x1208, 0x00E0, 0x00E0]);
+    }
+
+    #[test]
+    fn data_directives_store_bytes_words_and_strings() {
+        let rom: Vec<u8> = assemble(
+            "db 1, 0x02, -1, \"Hi; there\"\n\
+             dw 0x1234, label\n\
+             label: db \"a,b\" ; a comment"
+        );
+        let mut expected: Vec<u8> = vec![1, 2, 0xFF];
+        expected.extend_from_slice(b"Hi; there");
+        expected.extend_from_slice(&[0x12, 0x34, 0x02, 0x10]);
+        expected.extend_from_slice(b"a,b");
+        assert_eq!(rom, expected);
+    }
+
+    #[test]
+    fn included_files_are_resolved_relative_to_the_including_file() {
+        let directory: PathBuf = std::env::temp_dir().join(format!("rusty_chip8_include_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("lib")).unwrap();
+        std::fs::write(directory.join("main.8o"), "include \"lib/sprites.8o\"\nLD I, sprite\nJP 0x200").unwrap();
+        std::fs::write(directory.join("lib/sprites.8o"), "include \"consts.8o\"\nJP SKIP\nsprite: db 0x3C").unwrap();
+        std::fs::write(directory.join("lib/consts.8o"), "SKIP = 0x206").unwrap();
+        std::fs::write(directory.join("self.8o"), "include \"self.8o\"").unwrap();
+
+        let rom: Result<Vec<u8>, AssemblyError> = Assembler::assemble_file(&directory.join("main.8o"));
+        let recursive: Result<Vec<u8>, AssemblyError> = Assembler::assemble_file(&directory.join("self.8o"));
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(rom.unwrap(), [0x12, 0x06, 0x3C, 0xA2, 0x02, 0x12, 0x00]);
+        assert!(recursive.unwrap_err().message.ends_with("includes itself"));
+    }
+
+    #[test]
+    fn undefined_label_points_at_the_operand() {
+        let error: AssemblyError = assembly_error("CLS\n  JP  missing");
+        assert_eq!((error.line, error.column), (2, 7));
+        assert_eq!(error.message, "Undefined label or constant: missing");
+        assert_eq!(error.to_string(), "test.8o:2:7: Undefined label or constant: missing");
+    }
+
+    #[test]
+    fn out_of_range_value_points_at_the_operand() {
+        let error: AssemblyError = assembly_error("\n\nLD V0, 0x100");
+        assert_eq!((error.line, error.column), (3, 8));
+        assert_eq!(error.message, "Value 256 is out of range (-128 to 255)");
+
+        let error: AssemblyError = assembly_error("DRW V0, V1, 16");
+        assert_eq!((error.line, error.column), (1, 13));
+        assert_eq!(error.message, "Value 16 is out of range (0 to 15)");
+    }
+
+    #[test]
+    fn bad_register_points_at_the_statement() {
+        let error: AssemblyError = assembly_error("CLS\nlabel: ADD VG, V1");
+        assert_eq!((error.line, error.column), (2, 8));
+        assert_eq!(error.message, "Invalid operands for ADD");
+
+        let error: AssemblyError = assembly_error("VA: CLS");
+        assert_eq!((error.line, error.column), (1, 1));
+        assert_eq!(error.message, "Invalid label or constant name: VA");
+    }
+
+    #[test]
+    fn unknown_instructions_and_duplicate_labels_are_reported() {
+        assert_eq!(assembly_error("MOV V0, V1").message, "Unknown instruction: MOV");
+        assert_eq!(assembly_error("a: CLS\na: CLS").message, "a is already defined");
+        assert_eq!(assembly_error("db").message, "Data directive needs at least one value");
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::instruction::{self, Instruction};
+use crate::chip8::platform::Platform;
+
+// ROMs are loaded at 0x200
+const PROGRAM_START: u16 = 0x200;
+
+// Bytes of a ROM that fit below the end of the 64 KiB address space
+const MAX_ROM_SIZE: usize = 0x10000 - PROGRAM_START as usize;
+
+// Number of data bytes written per db directive
+const BYTES_PER_DATA_LINE: usize = 8;
+
+// Column the address comments start at
+const COMMENT_COLUMN: usize = 32;
+
+/// The kind of label generated for an address, in order of precedence
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Jump,
+    Subroutine,
+}
+
+/// Turns a ROM into Cowgod-style mnemonics.
+/// Control flow is followed from 0x200 to tell code apart from sprite data,
+///     and labels are generated for jump, call and I targets inside the ROM.
+pub struct Disassembler<'a> {
+    // The part of the ROM that can be addressed, anything past 0xFFFF is left out
+    rom: &'a [u8],
+    ignored_bytes: usize,
+    platform: Platform,
+    instructions: BTreeMap<u16, Instruction>,
+    labels: BTreeMap<u16, LabelKind>,
+}
+
+impl<'a> Disassembler<'a> {
+    /// Disassemble a ROM, only decoding the instructions available on `platform`
+    pub fn new(rom: &'a [u8], platform: Platform) -> Self {
+        let addressable: usize = rom.len().min(MAX_ROM_SIZE);
+        let mut disassembler: Disassembler = Disassembler {
+            rom: &rom[..addressable],
+            ignored_bytes: rom.len() - addressable,
+            platform,
+            instructions: BTreeMap::new(),
+            labels: BTreeMap::new(),
+        };
+        disassembler.trace(PROGRAM_START);
+        disassembler
+    }
+
+    /// Get the instruction decoded at an address, if the address was reached as code
+    pub fn instruction_at(&self, address: u16) -> Option<&Instruction> {
+        self.instructions.get(&address)
+    }
+
+    /// Get the label generated for an address, if there is one
+    pub fn label_at(&self, address: u16) -> Option<String> {
+        let prefix: &str = match self.labels.get(&address)? {
+            LabelKind::Data => "data",
+            LabelKind::Jump => "loc",
+            LabelKind::Subroutine => "sub",
+        };
+        Some(format!("{}_{:03X}", prefix, address))
+    }
+
+    /// Produce the disassembly as assembler source
+    pub fn to_source(&self) -> String {
+        let mut source: String = String::new();
+        let end: u32 = self.end();
+        let written: BTreeMap<u32, u32> = self.written_instructions();
+        let code_bytes: usize = written.iter().map(|(start, end)| (end - start) as usize).sum();
+        writeln!(source, "; {:#05X} - {:#05X}", PROGRAM_START, end.saturating_sub(1)).unwrap();
+        writeln!(
+            source,
+            "; {} instructions, {} data bytes",
+            written.len(),
+            self.rom.len() - code_bytes
+        ).unwrap();
+        if self.ignored_bytes > 0 {
+            writeln!(source, "; {} bytes past 0xFFFF can't be addressed and were left out", self.ignored_bytes).unwrap();
+        }
+
+        // Addresses are below the end of the ROM, which is at most 0x10000, so they fit in 16 bits
+        let mut address: u32 = PROGRAM_START as u32;
+        while address < end {
+            if let Some(label) = self.label_at(address as u16) {
+                writeln!(source, "\n{}:", label).unwrap();
+            }
+
+            if let Some(instruction) = self.instructions.get(&(address as u16)) {
+                let text: String = instruction.format_with(&|target| self.format_address(target));
+                let words: Vec<String> = instruction.encode().iter().map(|w| format!("{:04X}", w)).collect();
+                writeln!(source, "{}", Self::with_comment(&text, &format!("{:#05X}: {}", address, words.join(" ")))).unwrap();
+                address += instruction.length() as u32;
+                continue;
+            }
+
+            // Group data bytes until the next label, instruction or line break
+            let start: u32 = address;
+            let mut bytes: Vec<String> = Vec::new();
+            while address < end
+                && bytes.len() < BYTES_PER_DATA_LINE
+                && !self.instructions.contains_key(&(address as u16))
+                && (address == start || !self.labels.contains_key(&(address as u16))) {
+                bytes.push(format!("{:#04X}", self.byte_at(address)));
+                address += 1;
+            }
+            let text: String = format!("db {}", bytes.join(", "));
+            writeln!(source, "{}", Self::with_comment(&text, &format!("{:#05X}", start))).unwrap();
+        }
+
+        source
+    }
+
+    /// Follow control flow from an address, decoding every instruction that can be reached
+    fn trace(&mut self, entry: u16) {
+        // Addresses are followed as 32 bits so stepping past the last instruction of a 64 KiB ROM can't overflow,
+        //     decode_at rejects anything past the end of the ROM
+        let mut pending: Vec<u32> = vec![entry as u32];
+        while let Some(address) = pending.pop() {
+            let instruction: Instruction = match self.decode_at(address) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            if self.instructions.insert(address as u16, instruction).is_some() {
+                continue;
+            }
+            let next: u32 = address + instruction.length() as u32;
+
+            match instruction {
+                Instruction::Jp(target) => {
+                    self.add_label(target, LabelKind::Jump);
+                    pending.push(target as u32);
+                }
+                Instruction::Call(target) => {
+                    self.add_label(target, LabelKind::Subroutine);
+                    pending.push(target as u32);
+                    pending.push(next);
+                }
+                Instruction::JpV0(target) => {
+                    // The jump table itself can't be followed without knowing V0
+                    self.add_label(target, LabelKind::Jump);
+                    pending.push(target as u32);
+                }
+                Instruction::Ret | Instruction::Exit => {}
+                Instruction::LdI(target) | Instruction::LdILong(target) => {
+                    self.add_label(target, LabelKind::Data);
+                    pending.push(next);
+                }
+                _ if instruction.is_skip() => {
+                    pending.push(next);
+                    let skipped_length: u32 = self.decode_at(next).map_or(2, |i| i.length() as u32);
+                    pending.push(next + skipped_length);
+                }
+                _ => pending.push(next),
+            }
+        }
+
+        // Targets that were reached as code keep their code label, data labels outside the ROM are dropped
+        let end: u32 = self.end();
+        self.labels.retain(|address, _| *address >= PROGRAM_START && (*address as u32) < end);
+
+        // Targets in the middle of a written instruction, like the second word of a long load or an odd address
+        //     jumped to inside one, don't start a line, so they are written as plain addresses instead
+        let written: BTreeMap<u32, u32> = self.written_instructions();
+        self.labels.retain(|&address, _| {
+            written.range(..=address as u32).next_back()
+                .is_none_or(|(&start, &end)| address as u32 == start || address as u32 >= end)
+        });
+    }
+
+    /// Start and end of every instruction that gets a line of its own,
+    ///     instructions decoded inside another one are written as part of it
+    fn written_instructions(&self) -> BTreeMap<u32, u32> {
+        let mut written: BTreeMap<u32, u32> = BTreeMap::new();
+        let mut covered_until: u32 = 0;
+        for (&address, instruction) in &self.instructions {
+            if address as u32 >= covered_until {
+                covered_until = address as u32 + instruction.length() as u32;
+                written.insert(address as u32, covered_until);
+            }
+        }
+        written
+    }
+
+    /// Address just past the last byte of the ROM
+    fn end(&self) -> u32 {
+        PROGRAM_START as u32 + self.rom.len() as u32
+    }
+
+    /// Decode the instruction at an address, if it lies inside the ROM
+    fn decode_at(&self, address: u32) -> Option<Instruction> {
+        let end: u32 = self.end();
+        if address < PROGRAM_START as u32 || address + 1 >= end {
+            return None;
+        }
+
+        let word: u16 = (self.byte_at(address) as u16) << 8 | self.byte_at(address + 1) as u16;
+        let next_word: u16 = if address + 3 < end {
+            (self.byte_at(address + 2) as u16) << 8 | self.byte_at(address + 3) as u16
+        } else {
+            0
+        };
+
+        let instruction: Instruction = Instruction::decode(word, next_word)?;
+        if !instruction.is_supported_by(self.platform) || address + instruction.length() as u32 > end {
+            return None;
+        }
+        Some(instruction)
+    }
+
+    fn add_label(&mut self, address: u16, kind: LabelKind) {
+        let label: &mut LabelKind = self.labels.entry(address).or_insert(kind);
+        *label = (*label).max(kind);
+    }
+
+    fn byte_at(&self, address: u32) -> u8 {
+        self.rom[(address - PROGRAM_START as u32) as usize]
+    }
+
+    fn format_address(&self, address: u16) -> String {
+        self.label_at(address).unwrap_or_else(|| instruction::format_address(address))
+    }
+
+    fn with_comment(text: &str, comment: &str) -> String {
+        format!("    {:<width$} ; {}", text, comment, width = COMMENT_COLUMN - 6)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assembler::Assembler;
+
+    fn rom(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn code_and_data_are_told_apart() {
+        let rom: Vec<u8> = [
+            rom(&[
+                0xA206, // LD I, data_206
+                0xD013, // DRW V0, V1, 3
+                0x1204, // JP loc_204
+            ]),
+            vec![0xF0, 0x90, 0xF0],
+        ].concat();
+        let disassembler: Disassembler = Disassembler::new(&rom, Platform::Chip8);
+
+        assert_eq!(disassembler.instruction_at(0x200), Some(&Instruction::LdI(0x206)));
+        assert_eq!(disassembler.instruction_at(0x204), Some(&Instruction::Jp(0x204)));
+        assert_eq!(disassembler.instruction_at(0x206), None);
+
+        let source: String = disassembler.to_source();
+        assert!(source.contains("LD I, data_206"));
+        assert!(source.contains("db 0xF0, 0x90, 0xF0"));
+        assert!(source.contains("; 3 instructions, 3 data bytes"));
+    }
+
+    #[test]
+    fn labels_are_named_by_their_strongest_use() {
+        let rom: Vec<u8> = rom(&[
+            0x220A, // CALL sub_20A
+            0x1208, // JP loc_208
+            0xA20C, // LD I, data_20C (unreachable)
+            0xA20A, // LD I, 0x20A
+            0x1208, // JP loc_208
+            0x120A, // JP sub_20A
+            0x00EE, // RET, also the target of LD I
+            0xA300, // LD I, 0x300 (outside the ROM)
+        ]);
+        let disassembler: Disassembler = Disassembler::new(&rom, Platform::Chip8);
+
+        assert_eq!(disassembler.label_at(0x20A).as_deref(), Some("sub_20A"));
+        assert_eq!(disassembler.label_at(0x208).as_deref(), Some("loc_208"));
+        assert_eq!(disassembler.label_at(0x202), None);
+        assert_eq!(disassembler.label_at(0x300), None);
+        assert_eq!(disassembler.instruction_at(0x204), None);
+    }
+
+    #[test]
+    fn skips_step_over_the_whole_long_load() {
+        let rom: Vec<u8> = rom(&[
+            0x3000, // SE V0, 0
+            0xF000, // LD I, long 0x0300
+            0x0300,
+            0x00FD, // EXIT
+        ]);
+        let disassembler: Disassembler = Disassembler::new(&rom, Platform::XoChip);
+        assert_eq!(disassembler.instruction_at(0x202), Some(&Instruction::LdILong(0x0300)));
+        assert_eq!(disassembler.instruction_at(0x204), None);
+        assert_eq!(disassembler.instruction_at(0x206), Some(&Instruction::Exit));
+
+        // F000 isn't an instruction before XO-CHIP, so the skip only steps over one word
+        let disassembler: Disassembler = Disassembler::new(&rom, Platform::SuperChip);
+        assert_eq!(disassembler.instruction_at(0x202), None);
+        assert_eq!(disassembler.instruction_at(0x204), Some(&Instruction::Sys(0x300)));
+    }
+
+    #[test]
+    fn largest_rom_is_disassembled() {
+        // Code running up to the last word of the 64 KiB address space
+        let rom: Vec<u8> = [0x60, 0x00].repeat(MAX_ROM_SIZE / 2);
+        let disassembler: Disassembler = Disassembler::new(&rom, Platform::XoChip);
+        assert_eq!(disassembler.instruction_at(0xFFFE), Some(&Instruction::LdByte(0, 0)));
+        assert!(disassembler.to_source().starts_with("; 0x200 - 0xFFFF\n"));
+
+        // Bytes that can't be addressed are left out
+        let oversized: Vec<u8> = [0x60, 0x00].repeat(MAX_ROM_SIZE / 2 + 2);
+        let source: String = Disassembler::new(&oversized, Platform::XoChip).to_source();
+        assert!(source.contains("; 4 bytes past 0xFFFF can't be addressed and were left out"));
+        assert!(source.starts_with("; 0x200 - 0xFFFF\n"));
+    }
+
+    #[test]
+    fn targets_inside_an_instruction_are_written_as_addresses() {
+        let rom: Vec<u8> = rom(&[
+            0xF000, // LD I, long 0x00EE
+            0x00EE, // also RET, the target of the call
+            0x2202, // CALL 0x202
+            0x1207, // JP 0x207, in the middle of itself
+            0x00FD, // EXIT
+        ]);
+        let disassembler: Disassembler = Disassembler::new(&rom, Platform::XoChip);
+        assert_eq!(disassembler.instruction_at(0x202), Some(&Instruction::Ret));
+        assert_eq!(disassembler.label_at(0x202), None);
+        assert_eq!(disassembler.label_at(0x207), None);
+
+        let disassembly: String = disassembler.to_source();
+        assert!(disassembly.contains("CALL 0x202"));
+        assert!(disassembly.contains("JP 0x207"));
+        assert!(disassembly.contains("; 3 instructions, 2 data bytes"));
+        let reassembled: Vec<u8> = Assembler::assemble_source(&disassembly, "disassembly.asm")
+            .unwrap_or_else(|e| panic!("{}\n{}", e, disassembly));
+        assert_eq!(reassembled, rom);
+    }
+
+    #[test]
+    fn disassembly_assembles_to_the_same_rom() {
+        let source: &str = "
+                CLS
+                LD I, sprite
+                LD V0, 10
+                LD V1, 0x0C
+            loop:
+                DRW V0, V1, 5
+                CALL move
+                SE V0, 60
+                JP loop
+                LD I, long sprite
+                EXIT
+            move:
+                ADD V0, 1
+                SKP V2
+                SHR V3, V4
+                RET
+            sprite:
+                db 0xF0, 0x90, 0x90, 0x90, 0xF0, 0x01
+        ";
+        let rom: Vec<u8> = Assembler::assemble_source(source, "test.asm").unwrap();
+        let disassembly: String = Disassembler::new(&rom, Platform::XoChip).to_source();
+        let reassembled: Vec<u8> = Assembler::assemble_source(&disassembly, "disassembly.asm")
+            .unwrap_or_else(|e| panic!("{}\n{}", e, disassembly));
+        assert_eq!(reassembled, rom);
+    }
+}
@@ -0,0 +1,381 @@
+use std::fmt;
+
+use crate::chip8::op_code::OpCode;
+use crate::chip8::platform::Platform;
+
+/// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction.
+/// Registers are stored as their index, 0x0 through 0xF.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0nnn - SYS addr
+    Sys(u16),
+    /// 00E0 - CLS
+    Cls,
+    /// 00EE - RET
+    Ret,
+    /// 00Cn - SCD nibble
+    Scd(u8),
+    /// 00FB - SCR
+    Scr,
+    /// 00FC - SCL
+    Scl,
+    /// 00FD - EXIT
+    Exit,
+    /// 00FE - LOW
+    Low,
+    /// 00FF - HIGH
+    High,
+    /// 1nnn - JP addr
+    Jp(u16),
+    /// 2nnn - CALL addr
+    Call(u16),
+    /// 3xkk - SE Vx, byte
+    SeByte(u8, u8),
+    /// 4xkk - SNE Vx, byte
+    SneByte(u8, u8),
+    /// 5xy0 - SE Vx, Vy
+    SeRegister(u8, u8),
+    /// 5xy2 - LD [I], Vx-Vy
+    SaveRange(u8, u8),
+    /// 5xy3 - LD Vx-Vy, [I]
+    LoadRange(u8, u8),
+    /// 6xkk - LD Vx, byte
+    LdByte(u8, u8),
+    /// 7xkk - ADD Vx, byte
+    AddByte(u8, u8),
+    /// 8xy0 - LD Vx, Vy
+    LdRegister(u8, u8),
+    /// 8xy1 - OR Vx, Vy
+    Or(u8, u8),
+    /// 8xy2 - AND Vx, Vy
+    And(u8, u8),
+    /// 8xy3 - XOR Vx, Vy
+    Xor(u8, u8),
+    /// 8xy4 - ADD Vx, Vy
+    AddRegister(u8, u8),
+    /// 8xy5 - SUB Vx, Vy
+    Sub(u8, u8),
+    /// 8xy6 - SHR Vx, Vy
+    Shr(u8, u8),
+    /// 8xy7 - SUBN Vx, Vy
+    Subn(u8, u8),
+    /// 8xyE - SHL Vx, Vy
+    Shl(u8, u8),
+    /// 9xy0 - SNE Vx, Vy
+    SneRegister(u8, u8),
+    /// Annn - LD I, addr
+    LdI(u16),
+    /// Bnnn - JP V0, addr
+    JpV0(u16),
+    /// Cxkk - RND Vx, byte
+    Rnd(u8, u8),
+    /// Dxyn - DRW Vx, Vy, nibble
+    Drw(u8, u8, u8),
+    /// Ex9E - SKP Vx
+    Skp(u8),
+    /// ExA1 - SKNP Vx
+    Sknp(u8),
+    /// F000 nnnn - LD I, long addr
+    LdILong(u16),
+    /// Fn01 - PLANE n
+    Plane(u8),
+    /// F002 - AUDIO
+    Audio,
+    /// Fx07 - LD Vx, DT
+    LdVxDt(u8),
+    /// Fx0A - LD Vx, K
+    LdVxK(u8),
+    /// Fx15 - LD DT, Vx
+    LdDtVx(u8),
+    /// Fx18 - LD ST, Vx
+    LdStVx(u8),
+    /// Fx1E - ADD I, Vx
+    AddI(u8),
+    /// Fx29 - LD F, Vx
+    LdF(u8),
+    /// Fx30 - LD HF, Vx
+    LdHf(u8),
+    /// Fx33 - LD B, Vx
+    LdB(u8),
+    /// Fx3A - PITCH Vx
+    Pitch(u8),
+    /// Fx55 - LD [I], Vx
+    Save(u8),
+    /// Fx65 - LD Vx, [I]
+    Load(u8),
+    /// Fx75 - LD R, Vx
+    SaveFlags(u8),
+    /// Fx85 - LD Vx, R
+    LoadFlags(u8),
+}
+
+impl Instruction {
+    /// Decode an instruction. `next_word` is the word following it in memory,
+    ///     which is only used by the 4 byte long F000 nnnn instruction.
+    /// Returns None if the word isn't a known instruction.
+    pub fn decode(word: u16, next_word: u16) -> Option<Instruction> {
+        let op_code: OpCode = OpCode::new(word);
+        let x: u8 = op_code.get_x() as u8;
+        let y: u8 = op_code.get_y() as u8;
+        let n: u8 = op_code.get_nibble() as u8;
+        let kk: u8 = op_code.get_byte() as u8;
+        let nnn: u16 = op_code.get_nnn();
+
+        let instruction: Instruction = match op_code.split_op_code() {
+            (0x0, 0x0, 0xE, 0x0) => Instruction::Cls,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Ret,
+            (0x0, 0x0, 0xC, _) => Instruction::Scd(n),
+            (0x0, 0x0, 0xF, 0xB) => Instruction::Scr,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::Scl,
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::Low,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::High,
+            (0x0, _, _, _) if word != 0 => Instruction::Sys(nnn),
+            (0x1, _, _, _) => Instruction::Jp(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, _, _, _) => Instruction::SeByte(x, kk),
+            (0x4, _, _, _) => Instruction::SneByte(x, kk),
+            (0x5, _, _, 0x0) => Instruction::SeRegister(x, y),
+            (0x5, _, _, 0x2) => Instruction::SaveRange(x, y),
+            (0x5, _, _, 0x3) => Instruction::LoadRange(x, y),
+            (0x6, _, _, _) => Instruction::LdByte(x, kk),
+            (0x7, _, _, _) => Instruction::AddByte(x, kk),
+            (0x8, _, _, 0x0) => Instruction::LdRegister(x, y),
+            (0x8, _, _, 0x1) => Instruction::Or(x, y),
+            (0x8, _, _, 0x2) => Instruction::And(x, y),
+            (0x8, _, _, 0x3) => Instruction::Xor(x, y),
+            (0x8, _, _, 0x4) => Instruction::AddRegister(x, y),
+            (0x8, _, _, 0x5) => Instruction::Sub(x, y),
+            (0x8, _, _, 0x6) => Instruction::Shr(x, y),
+            (0x8, _, _, 0x7) => Instruction::Subn(x, y),
+            (0x8, _, _, 0xE) => Instruction::Shl(x, y),
+            (0x9, _, _, 0x0) => Instruction::SneRegister(x, y),
+            (0xA, _, _, _) => Instruction::LdI(nnn),
+            (0xB, _, _, _) => Instruction::JpV0(nnn),
+            (0xC, _, _, _) => Instruction::Rnd(x, kk),
+            (0xD, _, _, _) => Instruction::Drw(x, y, n),
+            (0xE, _, 0x9, 0xE) => Instruction::Skp(x),
+            (0xE, _, 0xA, 0x1) => Instruction::Sknp(x),
+            (0xF, 0x0, 0x0, 0x0) => Instruction::LdILong(next_word),
+            (0xF, _, 0x0, 0x1) => Instruction::Plane(x),
+            (0xF, 0x0, 0x0, 0x2) => Instruction::Audio,
+            (0xF, _, 0x0, 0x7) => Instruction::LdVxDt(x),
+            (0xF, _, 0x0, 0xA) => Instruction::LdVxK(x),
+            (0xF, _, 0x1, 0x5) => Instruction::LdDtVx(x),
+            (0xF, _, 0x1, 0x8) => Instruction::LdStVx(x),
+            (0xF, _, 0x1, 0xE) => Instruction::AddI(x),
+            (0xF, _, 0x2, 0x9) => Instruction::LdF(x),
+            (0xF, _, 0x3, 0x0) => Instruction::LdHf(x),
+            (0xF, _, 0x3, 0x3) => Instruction::LdB(x),
+            (0xF, _, 0x3, 0xA) => Instruction::Pitch(x),
+            (0xF, _, 0x5, 0x5) => Instruction::Save(x),
+            (0xF, _, 0x6, 0x5) => Instruction::Load(x),
+            (0xF, _, 0x7, 0x5) => Instruction::SaveFlags(x),
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags(x),
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// Encode the instruction into its words. Only F000 nnnn is two words long.
+    pub fn encode(&self) -> Vec<u16> {
+        let xy = |base: u16, x: u8, y: u8| base | (x as u16) << 8 | (y as u16) << 4;
+        let xkk = |base: u16, x: u8, kk: u8| base | (x as u16) << 8 | kk as u16;
+        let x = |base: u16, x: u8| base | (x as u16) << 8;
+
+        let word: u16 = match *self {
+            Instruction::Sys(nnn) => nnn & 0x0FFF,
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::Scd(n) => 0x00C0 | (n & 0xF) as u16,
+            Instruction::Scr => 0x00FB,
+            Instruction::Scl => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Low => 0x00FE,
+            Instruction::High => 0x00FF,
+            Instruction::Jp(nnn) => 0x1000 | (nnn & 0x0FFF),
+            Instruction::Call(nnn) => 0x2000 | (nnn & 0x0FFF),
+            Instruction::SeByte(vx, kk) => xkk(0x3000, vx, kk),
+            Instruction::SneByte(vx, kk) => xkk(0x4000, vx, kk),
+            Instruction::SeRegister(vx, vy) => xy(0x5000, vx, vy),
+            Instruction::SaveRange(vx, vy) => xy(0x5002, vx, vy),
+            Instruction::LoadRange(vx, vy) => xy(0x5003, vx, vy),
+            Instruction::LdByte(vx, kk) => xkk(0x6000, vx, kk),
+            Instruction::AddByte(vx, kk) => xkk(0x7000, vx, kk),
+            Instruction::LdRegister(vx, vy) => xy(0x8000, vx, vy),
+            Instruction::Or(vx, vy) => xy(0x8001, vx, vy),
+            Instruction::And(vx, vy) => xy(0x8002, vx, vy),
+            Instruction::Xor(vx, vy) => xy(0x8003, vx, vy),
+            Instruction::AddRegister(vx, vy) => xy(0x8004, vx, vy),
+            Instruction::Sub(vx, vy) => xy(0x8005, vx, vy),
+            Instruction::Shr(vx, vy) => xy(0x8006, vx, vy),
+            Instruction::Subn(vx, vy) => xy(0x8007, vx, vy),
+            Instruction::Shl(vx, vy) => xy(0x800E, vx, vy),
+            Instruction::SneRegister(vx, vy) => xy(0x9000, vx, vy),
+            Instruction::LdI(nnn) => 0xA000 | (nnn & 0x0FFF),
+            Instruction::JpV0(nnn) => 0xB000 | (nnn & 0x0FFF),
+            Instruction::Rnd(vx, kk) => xkk(0xC000, vx, kk),
+            Instruction::Drw(vx, vy, n) => xy(0xD000, vx, vy) | (n & 0xF) as u16,
+            Instruction::Skp(vx) => x(0xE09E, vx),
+            Instruction::Sknp(vx) => x(0xE0A1, vx),
+            Instruction::LdILong(nnnn) => return vec![0xF000, nnnn],
+            Instruction::Plane(n) => x(0xF001, n),
+            Instruction::Audio => 0xF002,
+            Instruction::LdVxDt(vx) => x(0xF007, vx),
+            Instruction::LdVxK(vx) => x(0xF00A, vx),
+            Instruction::LdDtVx(vx) => x(0xF015, vx),
+            Instruction::LdStVx(vx) => x(0xF018, vx),
+            Instruction::AddI(vx) => x(0xF01E, vx),
+            Instruction::LdF(vx) => x(0xF029, vx),
+            Instruction::LdHf(vx) => x(0xF030, vx),
+            Instruction::LdB(vx) => x(0xF033, vx),
+            Instruction::Pitch(vx) => x(0xF03A, vx),
+            Instruction::Save(vx) => x(0xF055, vx),
+            Instruction::Load(vx) => x(0xF065, vx),
+            Instruction::SaveFlags(vx) => x(0xF075, vx),
+            Instruction::LoadFlags(vx) => x(0xF085, vx),
+        };
+
+        vec![word]
+    }
+
+    /// The first platform that supports this instruction
+    pub fn platform(&self) -> Platform {
+        match self {
+            Instruction::Scd(_)
+            | Instruction::Scr
+            | Instruction::Scl
+            | Instruction::Exit
+            | Instruction::Low
+            | Instruction::High
+            | Instruction::LdHf(_)
+            | Instruction::SaveFlags(_)
+            | Instruction::LoadFlags(_) => Platform::SuperChip,
+            Instruction::SaveRange(..)
+            | Instruction::LoadRange(..)
+            | Instruction::LdILong(_)
+            | Instruction::Plane(_)
+            | Instruction::Audio
+            | Instruction::Pitch(_) => Platform::XoChip,
+            Instruction::Drw(_, _, 0) => Platform::SuperChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    /// Whether the instruction can run on a platform
+    pub fn is_supported_by(&self, platform: Platform) -> bool {
+        match self.platform() {
+            Platform::Chip8 => true,
+            Platform::SuperChip => platform.has_schip_instructions(),
+            Platform::XoChip => platform.has_xochip_instructions(),
+        }
+    }
+
+    /// Length of the instruction in bytes
+    pub fn length(&self) -> u16 {
+        match self {
+            Instruction::LdILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Address this instruction jumps to, calls or points I at, if any
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jp(address)
+            | Instruction::Call(address)
+            | Instruction::JpV0(address)
+            | Instruction::LdI(address)
+            | Instruction::LdILong(address)
+            | Instruction::Sys(address) => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Whether the instruction may skip over the instruction after it
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SeByte(..)
+                | Instruction::SneByte(..)
+                | Instruction::SeRegister(..)
+                | Instruction::SneRegister(..)
+                | Instruction::Skp(_)
+                | Instruction::Sknp(_)
+        )
+    }
+
+    /// Format the instruction as a Cowgod-style mnemonic, using `address` to format address operands
+    pub fn format_with(&self, address: &dyn Fn(u16) -> String) -> String {
+        let byte = |kk: u8| format!("{:#04X}", kk);
+
+        match *self {
+            Instruction::Sys(nnn) => format!("SYS {}", address(nnn)),
+            Instruction::Cls => String::from("CLS"),
+            Instruction::Ret => String::from("RET"),
+            Instruction::Scd(n) => format!("SCD {}", n),
+            Instruction::Scr => String::from("SCR"),
+            Instruction::Scl => String::from("SCL"),
+            Instruction::Exit => String::from("EXIT"),
+            Instruction::Low => String::from("LOW"),
+            Instruction::High => String::from("HIGH"),
+            Instruction::Jp(nnn) => format!("JP {}", address(nnn)),
+            Instruction::Call(nnn) => format!("CALL {}", address(nnn)),
+            Instruction::SeByte(vx, kk) => format!("SE V{:X}, {}", vx, byte(kk)),
+            Instruction::SneByte(vx, kk) => format!("SNE V{:X}, {}", vx, byte(kk)),
+            Instruction::SeRegister(vx, vy) => format!("SE V{:X}, V{:X}", vx, vy),
+            Instruction::SaveRange(vx, vy) => format!("LD [I], V{:X}-V{:X}", vx, vy),
+            Instruction::LoadRange(vx, vy) => format!("LD V{:X}-V{:X}, [I]", vx, vy),
+            Instruction::LdByte(vx, kk) => format!("LD V{:X}, {}", vx, byte(kk)),
+            Instruction::AddByte(vx, kk) => format!("ADD V{:X}, {}", vx, byte(kk)),
+            Instruction::LdRegister(vx, vy) => format!("LD V{:X}, V{:X}", vx, vy),
+            Instruction::Or(vx, vy) => format!("OR V{:X}, V{:X}", vx, vy),
+            Instruction::And(vx, vy) => format!("AND V{:X}, V{:X}", vx, vy),
+            Instruction::Xor(vx, vy) => format!("XOR V{:X}, V{:X}", vx, vy),
+            Instruction::AddRegister(vx, vy) => format!("ADD V{:X}, V{:X}", vx, vy),
+            Instruction::Sub(vx, vy) => format!("SUB V{:X}, V{:X}", vx, vy),
+            Instruction::Shr(vx, vy) => format!("SHR V{:X}, V{:X}", vx, vy),
+            Instruction::Subn(vx, vy) => format!("SUBN V{:X}, V{:X}", vx, vy),
+            Instruction::Shl(vx, vy) => format!("SHL V{:X}, V{:X}", vx, vy),
+            Instruction::SneRegister(vx, vy) => format!("SNE V{:X}, V{:X}", vx, vy),
+            Instruction::LdI(nnn) => format!("LD I, {}", address(nnn)),
+            Instruction::JpV0(nnn) => format!("JP V0, {}", address(nnn)),
+            Instruction::Rnd(vx, kk) => format!("RND V{:X}, {}", vx, byte(kk)),
+            Instruction::Drw(vx, vy, n) => format!("DRW V{:X}, V{:X}, {}", vx, vy, n),
+            Instruction::Skp(vx) => format!("SKP V{:X}", vx),
+            Instruction::Sknp(vx) => format!("SKNP V{:X}", vx),
+            Instruction::LdILong(nnnn) => format!("LD I, LONG {}", address(nnnn)),
+            Instruction::Plane(n) => format!("PLANE {}", n),
+            Instruction::Audio => String::from("AUDIO"),
+            Instruction::LdVxDt(vx) => format!("LD V{:X}, DT", vx),
+            Instruction::LdVxK(vx) => format!("LD V{:X}, K", vx),
+            Instruction::LdDtVx(vx) => format!("LD DT, V{:X}", vx),
+            Instruction::LdStVx(vx) => format!("LD ST, V{:X}", vx),
+            Instruction::AddI(vx) => format!("ADD I, V{:X}", vx),
+            Instruction::LdF(vx) => format!("LD F, V{:X}", vx),
+            Instruction::LdHf(vx) => format!("LD HF, V{:X}", vx),
+            Instruction::LdB(vx) => format!("LD B, V{:X}", vx),
+            Instruction::Pitch(vx) => format!("PITCH V{:X}", vx),
+            Instruction::Save(vx) => format!("LD [I], V{:X}", vx),
+            Instruction::Load(vx) => format!("LD V{:X}, [I]", vx),
+            Instruction::SaveFlags(vx) => format!("LD R, V{:X}", vx),
+            Instruction::LoadFlags(vx) => format!("LD V{:X}, R", vx),
+        }
+    }
+}
+
+/// Format an address as hexadecimal, using 3 digits unless it only fits in 4
+pub fn format_address(address: u16) -> String {
+    if address > 0xFFF {
+        format!("{:#06X}", address)
+    } else {
+        format!("{:#05X}", address)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format_with(&format_address))
+    }
+}
@@ -1,27 +1,58 @@
 use crate::debug::debugger::Debugger;
-use super::{font::FONT_SET, op_code::OpCode};
+use crate::debug::profiler::Profile;
+use crate::debug::trace::Tracer;
+use crate::error::Chip8Error;
+use super::{font::{BIG_FONT_SET, FONT_SET}, op_code::OpCode, opcode_policy::OpcodePolicy, platform::Platform, quirks::Quirks, rng::Chip8Rng};
+use super::state::{self, StateReader, StateWriter};
 
-use rand;
-use rand::Rng;
-use std::time;
+use std::ops::Range;
 
-const MEMORY: usize = 4096;
 const V_REGISTER_COUNT: usize = 16;
 const STACK_SIZE: usize = 16;
 const PROGRAM_START: usize = 0x200;
-const VRAM_WIDTH: usize = 64;
-const VRAM_HEIGHT: usize = 32;
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+pub const VRAM_SIZE: usize = HIRES_WIDTH * HIRES_HEIGHT;
 const SPRITE_LENGTH: u8 = 5;
+const BIG_FONT_START: usize = FONT_SET.len();
+const BIG_SPRITE_LENGTH: u8 = 10;
+const RPL_FLAG_COUNT: usize = 16;
 const TOTAL_KEYS: usize = 16;
-const TICKS_PER_CYCLE: u16 = 8;
+const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64;
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u16 = 8;
 
 // Sprites have 8 columns and can be up to 15 rows high
 const SPRITE_WIDTH: u16 = 8;
 
+// SUPER-CHIP Dxy0 sprites are 16x16
+const BIG_SPRITE_SIZE: u16 = 16;
+
+/// Runs a COSMAC 1802 machine code routine called by 0nnn, given the machine and the address of the routine.
+/// The hook can emulate the routine through the public interface of the machine, or fail with an error.
+pub type MachineCodeHook = Box<dyn FnMut(&mut CHIP8, u16) -> Result<(), Chip8Error>>;
+
+/// Where a frame run by CHIP8::run_frame_with is, passed to its hook
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameEvent {
+    /// The instruction at this address is about to run
+    Before(u16),
+    /// The instruction at this address has run
+    After(u16),
+}
+
 // CHIP-8 Interpreter
 pub struct CHIP8 {
-    ram: [u8; MEMORY],
-    pub vram: [u8; VRAM_WIDTH * VRAM_HEIGHT],
+    ram: Vec<u8>,
+    pub vram: [u8; VRAM_SIZE], // each pixel holds one bit per XO-CHIP bitplane
+    hires: bool,
+    planes: u8, // bitplanes selected by Fn01
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    rpl: [u8; RPL_FLAG_COUNT],
+    exited: bool,
     v: [u8; V_REGISTER_COUNT],
     i: u16,
     delay_timer: u8,
@@ -30,41 +61,157 @@ pub struct CHIP8 {
     stack_pointer: u8,
     stack: [u16; STACK_SIZE],
     keys: [bool; TOTAL_KEYS],
-    timer_time: time::Instant,
+    instructions_per_frame: u16,
+    platform: Platform,
+    quirks: Quirks,
+    vblank_wait: bool,
+    rng: Chip8Rng,
+    last_write: Option<Range<usize>>, // memory written by the last instruction
+    opcode_policy: OpcodePolicy,
+    machine_code_hook: Option<MachineCodeHook>,
+    tracer: Option<Tracer>,
+    profile: Option<Profile>,
 }
 
 impl CHIP8 {
-    pub fn new() -> Self {
+    pub fn new(platform: Platform, quirks: Quirks) -> Self {
         // Load font data to the interpreter area (registers 0x000 to 0x1FF)
-        let mut ram: [u8; MEMORY] = [0; MEMORY];
+        let mut ram: Vec<u8> = vec![0; platform.memory_size()];
         for (index, font_char_piece) in FONT_SET.iter().enumerate() {
             ram[index] = *font_char_piece;
         }
+        for (index, font_char_piece) in BIG_FONT_SET.iter().enumerate() {
+            ram[BIG_FONT_START + index] = *font_char_piece;
+        }
 
         CHIP8 {
             ram,
             i: 0,
             v: [0; V_REGISTER_COUNT],
-            vram: [0; (VRAM_WIDTH * VRAM_HEIGHT)],
+            vram: [0; VRAM_SIZE],
+            hires: false,
+            planes: 1,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            rpl: [0; RPL_FLAG_COUNT],
+            exited: false,
             delay_timer: 0,
             sound_timer: 0,
             pc: PROGRAM_START as u16,
             stack_pointer: 0,
             stack: [0; STACK_SIZE],
             keys: [false; TOTAL_KEYS],
-            timer_time: time::Instant::now()
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            platform,
+            quirks,
+            vblank_wait: false,
+            rng: Chip8Rng::from_entropy(),
+            last_write: None,
+            opcode_policy: OpcodePolicy::default(),
+            machine_code_hook: None,
+            tracer: None,
+            profile: None,
         }
     }
 
     /// Loads ROM data to the interpreter
-    pub fn load_rom_data(&mut self, data: &[u8]) {
-        if data.len() > (MEMORY - PROGRAM_START) {
-            panic!("ERROR: Rom data exceeds {} bytes. Exiting...", MEMORY);
+    pub fn load_rom_data(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let capacity: usize = self.ram.len() - PROGRAM_START;
+        if data.len() > capacity {
+            return Err(Chip8Error::RomTooLarge { size: data.len(), capacity });
         }
 
         for (i, op_data) in data.iter().enumerate() {
             self.ram[PROGRAM_START + i] = *op_data;
         }
+        Ok(())
+    }
+
+    /// Write data to memory, e.g. to set options a test ROM reads from the interpreter area
+    pub fn write_memory(&mut self, address: usize, data: &[u8]) -> Result<(), Chip8Error> {
+        self.check_memory(address, data.len())?;
+        self.ram[address..address + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Get the platform the interpreter emulates
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Get the program counter
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Get the index register I
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    /// Get the general purpose registers V0 through VF
+    pub fn registers(&self) -> &[u8; V_REGISTER_COUNT] {
+        &self.v
+    }
+
+    /// Get the return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    /// Get the number of return addresses on the stack
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    /// Get the delay timer
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Get the whole addressable memory
+    pub fn memory(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Get the instruction at the program counter
+    pub fn current_op_code(&self) -> Result<u16, Chip8Error> {
+        self.get_op_code()
+    }
+
+    /// Get the memory addresses written by the last instruction, if it wrote any
+    pub fn last_write(&self) -> Option<Range<usize>> {
+        self.last_write.clone()
+    }
+
+    /// Release every key on the keypad
+    pub fn release_keys(&mut self) {
+        self.keys = [false; TOTAL_KEYS];
+    }
+
+    /// Width of the display in the current resolution mode
+    pub fn display_width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { LORES_WIDTH }
+    }
+
+    /// Height of the display in the current resolution mode
+    pub fn display_height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { LORES_HEIGHT }
+    }
+
+    /// The XO-CHIP audio pattern set by F002, if the program has set one
+    pub fn audio_pattern(&self) -> Option<[u8; AUDIO_PATTERN_SIZE]> {
+        self.audio_pattern
+    }
+
+    /// Playback rate of the XO-CHIP audio pattern in bits per second, set by Fx3A
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Whether the program has stopped itself with 00FD
+    pub fn has_exited(&self) -> bool {
+        self.exited
     }
 
     /// Press a key on the 16-character keypad
@@ -72,20 +219,255 @@ impl CHIP8 {
         self.keys[key] = is_pressed;
     }
 
-    pub fn tick(&mut self) {
-        let now = time::Instant::now();
+    /// Get the random number generator used by Cxkk
+    pub fn rng(&self) -> &Chip8Rng {
+        &self.rng
+    }
+
+    /// Replace the random number generator used by Cxkk, e.g. with a seeded one for reproducible runs
+    pub fn set_rng(&mut self, rng: Chip8Rng) {
+        self.rng = rng;
+    }
+
+    /// What the interpreter does with instructions the platform doesn't support
+    pub fn opcode_policy(&self) -> OpcodePolicy {
+        self.opcode_policy
+    }
+
+    /// Set what the interpreter does with instructions the platform doesn't support.
+    /// This includes 0nnn on the COSMAC VIP when no machine code hook is set.
+    pub fn set_opcode_policy(&mut self, opcode_policy: OpcodePolicy) {
+        self.opcode_policy = opcode_policy;
+    }
+
+    /// Run machine code routines called by 0nnn with a hook.
+    /// Without a hook 0nnn follows the opcode policy like an unknown opcode.
+    pub fn set_machine_code_hook(&mut self, hook: Option<MachineCodeHook>) {
+        self.machine_code_hook = hook;
+    }
+
+    /// Trace executed instructions, or stop tracing with None
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Stop tracing and get the tracer back, e.g. to flush it
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Start counting where the program spends its cycles, resetting any previous counts
+    pub fn start_profiling(&mut self) {
+        self.profile = Some(Profile::new(self.ram.len()));
+    }
+
+    /// The counters collected since profiling started
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Stop profiling and get the counters
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    /// Serialize the full machine state into the versioned binary save state format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer: StateWriter = StateWriter::new();
+        writer.write_u8(state::platform_to_u8(self.platform));
+        writer.write_u8(state::quirks_to_u8(self.quirks));
+        writer.write_u32(self.ram.len() as u32);
+        writer.write_bytes(&self.ram);
+        writer.write_bytes(&self.vram);
+        writer.write_bool(self.hires);
+        writer.write_u8(self.planes);
+        writer.write_bool(self.audio_pattern.is_some());
+        writer.write_bytes(&self.audio_pattern.unwrap_or_default());
+        writer.write_u8(self.pitch);
+        writer.write_bytes(&self.rpl);
+        writer.write_bool(self.exited);
+        writer.write_bytes(&self.v);
+        writer.write_u16(self.i);
+        writer.write_u8(self.delay_timer);
+        writer.write_u8(self.sound_timer);
+        writer.write_u16(self.pc);
+        writer.write_u8(self.stack_pointer);
+        for address in self.stack {
+            writer.write_u16(address);
+        }
+        for key in self.keys {
+            writer.write_bool(key);
+        }
+        writer.write_u16(self.instructions_per_frame);
+        writer.write_bool(self.vblank_wait);
+        writer.write_u64(self.rng.seed());
+        writer.write_u64(self.rng.draws());
+        writer.write_u128(self.rng.word_position());
+        writer.finish()
+    }
+
+    /// Restore the full machine state from a save state created by save_state.
+    /// The machine is left untouched if the save state is invalid.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let mut reader: StateReader = StateReader::new(data)?;
+        let platform: Platform = state::platform_from_u8(reader.read_u8()?)?;
+        let quirks: Quirks = state::quirks_from_u8(reader.read_u8()?);
+        let ram_length: usize = reader.read_u32()? as usize;
+        if ram_length != platform.memory_size() {
+            return Err(Chip8Error::InvalidState("Save state memory size does not match its platform"));
+        }
+        let ram: Vec<u8> = reader.read_bytes(ram_length)?.to_vec();
+        let vram: [u8; VRAM_SIZE] = reader.read_array()?;
+        let hires: bool = reader.read_bool()?;
+        let planes: u8 = reader.read_u8()?;
+        let has_audio_pattern: bool = reader.read_bool()?;
+        let audio_pattern: [u8; AUDIO_PATTERN_SIZE] = reader.read_array()?;
+        let pitch: u8 = reader.read_u8()?;
+        let rpl: [u8; RPL_FLAG_COUNT] = reader.read_array()?;
+        let exited: bool = reader.read_bool()?;
+        let v: [u8; V_REGISTER_COUNT] = reader.read_array()?;
+        let i: u16 = reader.read_u16()?;
+        let delay_timer: u8 = reader.read_u8()?;
+        let sound_timer: u8 = reader.read_u8()?;
+        let pc: u16 = reader.read_u16()?;
+        let stack_pointer: u8 = reader.read_u8()?;
+        if stack_pointer as usize > STACK_SIZE {
+            return Err(Chip8Error::InvalidState("Save state has an invalid stack pointer"));
+        }
+        let mut stack: [u16; STACK_SIZE] = [0; STACK_SIZE];
+        for address in stack.iter_mut() {
+            *address = reader.read_u16()?;
+        }
+        let mut keys: [bool; TOTAL_KEYS] = [false; TOTAL_KEYS];
+        for key in keys.iter_mut() {
+            *key = reader.read_bool()?;
+        }
+        let instructions_per_frame: u16 = reader.read_u16()?;
+        let vblank_wait: bool = reader.read_bool()?;
+        let rng_seed: u64 = reader.read_u64()?;
+        let rng_draws: u64 = reader.read_u64()?;
+        let rng_word_position: u128 = reader.read_u128()?;
+        reader.finish()?;
+
+        self.platform = platform;
+        self.quirks = quirks;
+        self.ram = ram;
+        self.vram = vram;
+        self.hires = hires;
+        self.planes = planes;
+        self.audio_pattern = if has_audio_pattern { Some(audio_pattern) } else { None };
+        self.pitch = pitch;
+        self.rpl = rpl;
+        self.exited = exited;
+        self.v = v;
+        self.i = i;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.pc = pc;
+        self.stack_pointer = stack_pointer;
+        self.stack = stack;
+        self.keys = keys;
+        self.instructions_per_frame = instructions_per_frame;
+        self.vblank_wait = vblank_wait;
+        self.rng = Chip8Rng::restore(rng_seed, rng_draws, rng_word_position);
+        Ok(())
+    }
+
+    /// Number of instructions run_frame executes per frame
+    pub fn instructions_per_frame(&self) -> u16 {
+        self.instructions_per_frame
+    }
+
+    /// Set the number of instructions run_frame executes per frame
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u16) {
+        self.instructions_per_frame = instructions_per_frame;
+    }
+
+    /// Execute a single instruction.
+    /// On a fault the program counter is left at the faulting instruction.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        if self.exited {
+            return Ok(());
+        }
+
+        self.last_write = None;
+        let op_code_value: u16 = self.get_op_code()?;
+        let op_code: OpCode = OpCode::new(op_code_value);
+
+        // The instruction is described before it runs in case it overwrites itself
+        let traced: Option<(String, [u8; V_REGISTER_COUNT])> = match &self.tracer {
+            Some(tracer) if tracer.filter().matches(self.pc, op_code_value) => {
+                Some((format!("{:#06X}  {}", self.pc, Debugger::describe_instruction(self, self.pc)), self.v))
+            }
+            _ => None,
+        };
+
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record_instruction(self.pc, op_code_value);
+        }
+
+        let result: Result<(), Chip8Error> = self.run_instruction(&op_code);
+        if let (Some((description, registers)), Some(tracer)) = (traced, self.tracer.as_mut()) {
+            tracer.record(&description, &registers, &self.v, self.i);
+        }
+        result
+    }
+
+    /// Execute n instructions without touching the timers
+    pub fn run_cycles(&mut self, n: usize) -> Result<(), Chip8Error> {
+        for _i in 0..n {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Execute one 60 Hz frame: up to instructions_per_frame instructions followed by one timer decrement.
+    /// The frontend is responsible for calling this 60 times a second.
+    /// The original COSMAC VIP ran about 8 instructions per frame. With the display wait quirk
+    ///     the frame ends early as soon as a sprite has been drawn.
+    /// The timers are not decremented if an instruction faults.
+    pub fn run_frame(&mut self) -> Result<(), Chip8Error> {
+        self.run_frame_with(|_processor: &CHIP8, _event: FrameEvent| None::<()>)?;
+        Ok(())
+    }
+
+    /// Execute one 60 Hz frame like run_frame, calling `hook` before and after every instruction.
+    /// The frame ends early, without decrementing the timers, as soon as the hook returns a value,
+    ///     which is passed back to the caller. This is how the debugger stops on breakpoints.
+    pub fn run_frame_with<T>(&mut self, mut hook: impl FnMut(&CHIP8, FrameEvent) -> Option<T>) -> Result<Option<T>, Chip8Error> {
+        self.begin_frame();
+        for _i in 0..self.instructions_per_frame {
+            if self.is_waiting_for_vblank() || self.exited {
+                break;
+            }
+
+            let pc: u16 = self.pc;
+            if let Some(value) = hook(self, FrameEvent::Before(pc)) {
+                return Ok(Some(value));
+            }
+            self.step()?;
+            if let Some(value) = hook(self, FrameEvent::After(pc)) {
+                return Ok(Some(value));
+            }
+        }
 
-        // 1000 / 60 is 60 frames a second which is how quick the timers are supposed to run.
-        if now > (self.timer_time + time::Duration::from_millis(1000 / 60)) {
-            self.timers_tick();
-            self.timer_time = now;
+        self.timers_tick();
+        Ok(None)
+    }
 
-            // The original COSMAC VIP machine limited the frames per cycle to 8 at 60 kHz
-            // Super-CHIP8 is not capped to 60 kHz and is not bound to ticks per cycle 
-            self.cosmac_cycle_ticks(TICKS_PER_CYCLE);
+    /// Start a new frame, ending any wait for the vertical blank
+    fn begin_frame(&mut self) {
+        self.vblank_wait = false;
+        if let Some(profile) = self.profile.as_mut() {
+            profile.begin_frame();
         }
     }
 
+    /// Whether a draw has to wait for the next frame because of the display wait quirk
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.vblank_wait
+    }
+
     pub fn timers_tick(&mut self) {
         // Both timers decrease at a rate of 60 hz, so they will
         //    need to be decoupled from a CPU cycle tick
@@ -99,23 +481,64 @@ impl CHIP8 {
     }
 
     /// Get the current instruction and increase program counter to the next instruction
-    fn run_instruction(&mut self, op_code: &OpCode) {
-        Debugger::dprint_opcode(String::from("[INSTRUCTION]: "), op_code.get_instruction());
-
+    fn run_instruction(&mut self, op_code: &OpCode) -> Result<(), Chip8Error> {
         match op_code.split_op_code() {
             (0x0, 0x0, 0xE, 0x0) => {
                 // 00E0 - CLS
-                // Clear the display
+                // Clear the display (only the selected bitplanes on XO-CHIP)
                 for i in 0..self.vram.len() {
-                    self.vram[i] = 0;
+                    self.vram[i] &= !self.planes;
                 }
-                self.next_instruction();
+                self.next_instruction()?;
+            }
+            (0x0, 0x0, 0xC, _) if self.platform.has_schip_instructions() => {
+                // 00Cn - SCD nibble
+                // Scroll the display down by n pixels
+                let n: usize = op_code.get_nibble() as usize;
+                self.scroll_down(n);
+                self.next_instruction()?;
+            }
+            (0x0, 0x0, 0xF, 0xB) if self.platform.has_schip_instructions() => {
+                // 00FB - SCR
+                // Scroll the display right by 4 pixels
+                self.scroll_right(4);
+                self.next_instruction()?;
+            }
+            (0x0, 0x0, 0xF, 0xC) if self.platform.has_schip_instructions() => {
+                // 00FC - SCL
+                // Scroll the display left by 4 pixels
+                self.scroll_left(4);
+                self.next_instruction()?;
+            }
+            (0x0, 0x0, 0xF, 0xD) if self.platform.has_schip_instructions() => {
+                // 00FD - EXIT
+                // Exit the interpreter
+                self.exited = true;
+            }
+            (0x0, 0x0, 0xF, 0xE) if self.platform.has_schip_instructions() => {
+                // 00FE - LOW
+                // Switch to the 64x32 low resolution mode
+                self.set_hires(false);
+                self.next_instruction()?;
+            }
+            (0x0, 0x0, 0xF, 0xF) if self.platform.has_schip_instructions() => {
+                // 00FF - HIGH
+                // Switch to the 128x64 high resolution mode
+                self.set_hires(true);
+                self.next_instruction()?;
             }
             (0x0, 0x0, 0xE, 0xE) => {
                 // 00EE - RET
                 // Return from a subroutine
-                self.pc = self.stack_pop();
-                self.next_instruction();
+                self.pc = self.stack_pop()?;
+                self.next_instruction()?;
+            }
+            (0x0, _, _, _) if op_code.get_instruction() != 0 && !self.platform.has_schip_instructions() => {
+                // 0nnn - SYS addr
+                // Call the machine code routine at nnn
+                // Only the COSMAC VIP ran machine code, later platforms treat 0nnn as an unknown opcode
+                self.call_machine_code(op_code.get_nnn())?;
+                self.next_instruction()?;
             }
             (0x1, _, _, _) => {
                 // 1nnn - JP addr
@@ -127,7 +550,7 @@ impl CHIP8 {
                 // 2nnn - CALL addr
                 // Call subroutine at nnn
                 let nnn = op_code.get_nnn();
-                self.stack_push(self.pc);
+                self.stack_push(self.pc)?;
                 self.jump_to_instruction(nnn);
             }
             (0x3, _, _, _) => {
@@ -135,9 +558,9 @@ impl CHIP8 {
                 let kk: u8 = op_code.get_byte() as u8;
 
                 if self.v[x] == kk {
-                    self.skip_instruction();
+                    self.skip_instruction()?;
                 } else {
-                    self.next_instruction();
+                    self.next_instruction()?;
                 }
             }
             (0x4, _, _, _) => {
@@ -146,9 +569,9 @@ impl CHIP8 {
                 let x = op_code.get_x();
                 let kk = op_code.get_byte();
                 if self.v[x as usize] != kk as u8 {
-                    self.skip_instruction();
+                    self.skip_instruction()?;
                 } else {
-                    self.next_instruction();
+                    self.next_instruction()?;
                 }
             }
             (0x5, _, _, 0x0) => {
@@ -157,27 +580,50 @@ impl CHIP8 {
                 let x: usize = op_code.get_x() as usize;
                 let y: usize = op_code.get_y() as usize;
                 if self.v[x] == self.v[y] {
-                    self.skip_instruction();
+                    self.skip_instruction()?;
                 } else {
-                    self.next_instruction();
+                    self.next_instruction()?;
                 }
             }
+            (0x5, _, _, 0x2) if self.platform.has_xochip_instructions() => {
+                // 5xy2 - LD [I], Vx-Vy
+                // Store registers Vx through Vy in memory starting at location I. I is not changed.
+                let x: usize = op_code.get_x() as usize;
+                let y: usize = op_code.get_y() as usize;
+                self.check_memory(self.i as usize, x.abs_diff(y) + 1)?;
+                for (offset, register) in Self::register_range(x, y).enumerate() {
+                    self.ram[self.i as usize + offset] = self.v[register];
+                }
+                self.record_write(self.i as usize, x.abs_diff(y) + 1);
+                self.next_instruction()?;
+            }
+            (0x5, _, _, 0x3) if self.platform.has_xochip_instructions() => {
+                // 5xy3 - LD Vx-Vy, [I]
+                // Read registers Vx through Vy from memory starting at location I. I is not changed.
+                let x: usize = op_code.get_x() as usize;
+                let y: usize = op_code.get_y() as usize;
+                self.check_memory(self.i as usize, x.abs_diff(y) + 1)?;
+                for (offset, register) in Self::register_range(x, y).enumerate() {
+                    self.v[register] = self.ram[self.i as usize + offset];
+                }
+                self.next_instruction()?;
+            }
             (0x6, _, _, _) => {
                 // 6xkk - LD Vx, byte
                 // Set Vx = kk
                 self.v[op_code.get_x() as usize] = op_code.get_byte() as u8;
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0x7, _, _, _) => {
                 // 7xkk - ADD Vx, byte
                 // Set Vx = Vx + kk
-                let x: u16 = op_code.get_x() as u16;
-                let kk: u16 = op_code.get_byte() as u16;
+                let x: u16 = op_code.get_x();
+                let kk: u16 = op_code.get_byte();
 
-                let total: u16 = (self.v[x as usize] as u16) + (kk as u16);
+                let total: u16 = (self.v[x as usize] as u16) + kk;
                 self.v[x as usize] = total as u8;
 
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0x8, _, _, 0x0) => {
                 // 8xy0 - LD Vx, Vy
@@ -185,7 +631,7 @@ impl CHIP8 {
                 let x = op_code.get_x();
                 let y = op_code.get_y();
                 self.v[x as usize] = self.v[y as usize];
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0x8, _, _, 0x1) => {
                 // 8xy1 - OR Vx, Vy
@@ -194,9 +640,8 @@ impl CHIP8 {
                 let y: usize = op_code.get_y() as usize;
                 self.v[x] |= self.v[y];
 
-                // The original COSMAC VIP machine cleared VF
-                self.cosmac_clear_vf();
-                self.next_instruction();
+                self.quirk_clear_vf();
+                self.next_instruction()?;
             }
             (0x8, _, _, 0x2) => {
                 // 8xy2 - AND Vx, Vy
@@ -205,9 +650,8 @@ impl CHIP8 {
                 let y: usize = op_code.get_y() as usize;
                 self.v[x] &= self.v[y];
 
-                // The original COSMAC VIP machine cleared VF
-                self.cosmac_clear_vf();
-                self.next_instruction();
+                self.quirk_clear_vf();
+                self.next_instruction()?;
             }
             (0x8, _, _, 0x3) => {
                 // 8xy3 - XOR Vx, Vy
@@ -216,9 +660,8 @@ impl CHIP8 {
                 let y: usize = op_code.get_y() as usize;
                 self.v[x] ^= self.v[y];
 
-                // The original COSMAC VIP machine cleared VF
-                self.cosmac_clear_vf();
-                self.next_instruction();
+                self.quirk_clear_vf();
+                self.next_instruction()?;
             }
             (0x8, _, _, 0x4) => {
                 // 8xy4 - ADD Vx, Vy
@@ -229,7 +672,7 @@ impl CHIP8 {
                 self.v[x] = total as u8;
                 self.v[0x0f] = if total > 0xFF { 1 } else { 0 };
 
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0x8, _, _, 0x5) => {
                 // 8xy5 - SUB Vx, Vy
@@ -246,7 +689,7 @@ impl CHIP8 {
                     self.v[0xF] = 1;
                 }
 
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0x8, _, _, 0x6) => {
                 // 8xy6 - SHR Vx {, Vy}
@@ -254,30 +697,30 @@ impl CHIP8 {
                 let x: usize = op_code.get_x() as usize;
                 let y: usize = op_code.get_y() as usize;
 
-                // The original COSMAC VIP machine set VX to VY
-                // This instruction is ignored in Super-CHIP and Chip-48
-                self.cosmac_set_vx_to_vy(x, y);
+                self.quirk_set_vx_to_vy(x, y);
 
                 let shift: u8 = self.v[x] & 1;
 
                 self.v[x] >>= 1;
                 self.v[0xF] = shift;
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0x8, _, _, 0x7) => {
                 // 8xy7 - SUBN Vx, Vy
                 // Set Vx = Vy - Vx, set VF = NOT borrow.
                 let x: usize = op_code.get_x() as usize;
                 let y: usize = op_code.get_y() as usize;
-                self.v[x] = self.v[y].wrapping_sub(self.v[x]);
+                let (vx, borrow) = self.v[y].overflowing_sub(self.v[x]);
 
-                if self.v[y] > self.v[x] {
-                    self.v[0xF] = 1;
-                } else {
+                self.v[x] = vx;
+
+                if borrow {
                     self.v[0xF] = 0;
+                } else {
+                    self.v[0xF] = 1;
                 }
 
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0x8, _, _, 0xE) => {
                 // 8xyE - SHL Vx {, Vy}
@@ -285,15 +728,13 @@ impl CHIP8 {
                 let x: usize = op_code.get_x() as usize;
                 let y: usize = op_code.get_y() as usize;
 
-                // The original COSMAC VIP machine set VX to VY
-                // This instruction is ignored in Super-CHIP and Chip-48
-                self.cosmac_set_vx_to_vy(x, y);
+                self.quirk_set_vx_to_vy(x, y);
 
                 let shift = (self.v[x] >> 7) & 1;
 
                 self.v[x] <<= 1;
                 self.v[0xF] = shift;
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0x9, _, _, 0x0) => {
                 // 9xy0 - SNE Vx, Vy
@@ -301,102 +742,100 @@ impl CHIP8 {
                 let x: u16 = op_code.get_x();
                 let y: u16 = op_code.get_y();
                 if self.v[x as usize] != self.v[y as usize] {
-                    self.skip_instruction();
+                    self.skip_instruction()?;
                 } else {
-                    self.next_instruction();
+                    self.next_instruction()?;
                 }
             }
             (0xA, _, _, _) => {
                 // Annn - LD I, addr
                 // Set I = nnn
                 self.i = op_code.get_nnn();
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0xB, _, _, _) => {
                 // Bnnn - JP V0, addr
                 // Jump to location nnn + V0.
+                // With the jump quirk this becomes Bxnn - JP Vx, addr
                 let nnn: u16 = op_code.get_nnn();
-                self.jump_to_instruction(nnn + self.v[0] as u16)
+                let register: usize = if self.quirks.jump { op_code.get_x() as usize } else { 0 };
+                self.jump_to_instruction(nnn + self.v[register] as u16)
             }
             (0xC, _, _, _) => {
                 // Cxkk - RND Vx, byte
                 // Set Vx = random byte AND kk
                 let kk: u8 = op_code.get_byte() as u8;
                 let x: u16 = op_code.get_x();
-                let random_number: u8 = rand::thread_rng().gen_range(0..255);
+                let random_number: u8 = self.rng.next_byte();
                 self.v[x as usize] = random_number & kk;
-                self.next_instruction();
+                self.next_instruction()?;
+            }
+            (0xD, _, _, 0x0) if self.platform.has_schip_instructions() => {
+                // Dxy0 - DRW Vx, Vy, 0
+                // Display a 16x16 sprite starting at memory location I at (Vx, Vy), set VF = collision.
+                let x: usize = op_code.get_x() as usize;
+                let y: usize = op_code.get_y() as usize;
+                self.draw_sprite(x, y, BIG_SPRITE_SIZE, BIG_SPRITE_SIZE)?;
+                self.next_instruction()?;
             }
             (0xD, _, _, _) => {
                 // Dxyn - DRW Vx, Vy, nibble
                 // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
-                let x: u16 = op_code.get_x();
-                let y: u16 = op_code.get_y();
+                let x: usize = op_code.get_x() as usize;
+                let y: usize = op_code.get_y() as usize;
                 let nibble: u16 = op_code.get_nibble();
-
-                let vx = self.v[x as usize] as u16;
-                let vy = self.v[y as usize] as u16;
-
-                let mut bit_flipped = 0;
-
-                for i in 0..nibble {
-                    let address: u16 = self.i + i as u16;
-                    let pixel: u8 = self.ram[address as usize];
-                    for j in 0..SPRITE_WIDTH {
-                        if (pixel & (0b1000_0000 >> j)) != 0 {
-                            let x_coord: usize = (vx + j) as usize % VRAM_WIDTH;
-                            let y_coord: usize = (vy + i) as usize % VRAM_HEIGHT;
-                            let pixel_index = x_coord + VRAM_WIDTH * y_coord;
-                            bit_flipped |= self.vram[pixel_index];
-                            self.vram[pixel_index] ^= 1;
-
-                            // The original COSMAC VIP machine clips the sprite if it overflows off screen
-                            if self.cosmac_sprite_bounds_check(vx as u16, j as u16, VRAM_WIDTH as u16) {
-                                break;
-                            }
-                        }
-                    }
-
-                    // The original COSMAC VIP machine clips the sprite if it overflows off screen
-                    if self.cosmac_sprite_bounds_check(vy as u16, i as u16, VRAM_HEIGHT as u16) {
-                        break;
-                    }
-
-                }
-
-                if bit_flipped == 1 {
-                    self.v[0xF] = 1;
-                } else {
-                    self.v[0xF] = 0;
-                }
-                self.next_instruction();
+                self.draw_sprite(x, y, SPRITE_WIDTH, nibble)?;
+                self.next_instruction()?;
             }
             (0xE, _, 0x9, 0xE) => {
                 // Ex9E - SKP Vx
                 // Skip next instruction if key with the value of Vx is pressed
+                // Only the low nibble of Vx selects a key
                 let x: usize = op_code.get_x() as usize;
-                if self.keys[self.v[x] as usize] {
-                    self.skip_instruction();
+                if self.keys[(self.v[x] & 0xF) as usize] {
+                    self.skip_instruction()?;
                 } else {
-                    self.next_instruction();
+                    self.next_instruction()?;
                 }
             }
             (0xE, _, 0xA, 0x1) => {
                 // ExA1 - SKNP Vx
                 // Skip next instruction if key with the value of Vx is not pressed
                 let x: usize = op_code.get_x() as usize;
-                if !self.keys[self.v[x] as usize] {
-                    self.skip_instruction();
+                if !self.keys[(self.v[x] & 0xF) as usize] {
+                    self.skip_instruction()?;
                 } else {
-                    self.next_instruction();
+                    self.next_instruction()?;
                 }
             }
+            (0xF, 0x0, 0x0, 0x0) if self.platform.has_xochip_instructions() => {
+                // F000 nnnn - LD I, long addr
+                // Set I = the 16 bit address stored in the next two bytes
+                self.i = self.read_word(self.pc as usize + 2)?;
+                self.skip_instruction()?;
+            }
+            (0xF, _, 0x0, 0x1) if self.platform.has_xochip_instructions() => {
+                // Fn01 - PLANE n
+                // Select the bitplanes drawn to by CLS, DRW and the scroll instructions
+                self.planes = op_code.get_x() as u8 & 0b11;
+                self.next_instruction()?;
+            }
+            (0xF, 0x0, 0x0, 0x2) if self.platform.has_xochip_instructions() => {
+                // F002 - AUDIO
+                // Load the 16 byte audio pattern starting at location I
+                let start: usize = self.i as usize;
+                self.check_memory(start, AUDIO_PATTERN_SIZE)?;
+                let mut pattern: [u8; AUDIO_PATTERN_SIZE] = [0; AUDIO_PATTERN_SIZE];
+                pattern.copy_from_slice(&self.ram[start..start + AUDIO_PATTERN_SIZE]);
+                self.audio_pattern = Some(pattern);
+                self.next_instruction()?;
+            }
             (0xF, _, 0x0, 0x7) => {
                 // Fx07 - LD Vx, DT
                 // Set Vx = delay timer value
                 let x: u16 = op_code.get_x();
                 self.v[x as usize] = self.delay_timer;
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0xF, _, 0x0, 0xA) => {
                 // Fx0A - LD Vx, K
@@ -412,7 +851,9 @@ impl CHIP8 {
                 }
 
                 if is_pressed {
-                    self.next_instruction();
+                    self.next_instruction()?;
+                } else if let Some(profile) = self.profile.as_mut() {
+                    profile.record_key_wait();
                 }
             }
             (0xF, _, 0x1, 0x5) => {
@@ -420,85 +861,176 @@ impl CHIP8 {
                 // Set delay timer = Vx
                 let x: u16 = op_code.get_x();
                 self.delay_timer = self.v[x as usize];
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0xF, _, 0x1, 0x8) => {
                 // Fx18 - LD ST, Vx
                 // Set sound timer = Vx.
                 let x: u16 = op_code.get_x();
                 self.sound_timer = self.v[x as usize];
-                self.next_instruction();
+                self.next_instruction()?;
             }
             (0xF, _, 0x1, 0xE) => {
                 // Fx1E - ADD I, Vx
                 // Set I = I + Vx
                 let x: u16 = op_code.get_x();
-                self.i = self.i + self.v[x as usize] as u16;
-                self.next_instruction();
+                self.i = self.i.wrapping_add(self.v[x as usize] as u16);
+                self.next_instruction()?;
             }
             (0xF, _, 0x2, 0x9) => {
                 // Fx29 - LD F, Vx
                 // Set I = location of sprite for digit Vx.
+                // Only the low nibble of Vx selects a digit
+                let x: usize = op_code.get_x() as usize;
+                self.i = (self.v[x] & 0xF) as u16 * SPRITE_LENGTH as u16;
+                self.next_instruction()?;
+            }
+            (0xF, _, 0x3, 0x0) if self.platform.has_schip_instructions() => {
+                // Fx30 - LD HF, Vx
+                // Set I = location of the high resolution sprite for digit Vx.
                 let x: usize = op_code.get_x() as usize;
-                self.i = (self.v[x] * SPRITE_LENGTH) as u16;
-                self.next_instruction();
+                self.i = (BIG_FONT_START + (self.v[x] & 0xF) as usize * BIG_SPRITE_LENGTH as usize) as u16;
+                self.next_instruction()?;
+            }
+            (0xF, _, 0x3, 0xA) if self.platform.has_xochip_instructions() => {
+                // Fx3A - PITCH Vx
+                // Set the audio pattern playback rate to 4000 * 2 ^ ((Vx - 64) / 48) bits per second
+                let x: usize = op_code.get_x() as usize;
+                self.pitch = self.v[x];
+                self.next_instruction()?;
             }
             (0xF, _, 0x3, 0x3) => {
                 // Fx33 - LD B, Vx
                 // Store BCD representation of Vx in memory locations I, I+1, and I+2.
                 let x: usize = op_code.get_x() as usize;
                 let i: usize = self.i as usize;
+                self.check_memory(i, 3)?;
                 self.ram[i] = self.v[x] / 100;
                 self.ram[i + 1] = (self.v[x] % 100) / 10;
                 self.ram[i + 2] = self.v[x] % 10;
-                self.next_instruction();
+                self.record_write(i, 3);
+                self.next_instruction()?;
             }
             (0xF, _, 0x5, 0x5) => {
                 // Fx55 - LD [I], Vx
                 // Store registers V0 through Vx in memory starting at location I.
                 let x: u16 = op_code.get_x();
-                for index in 0..=x {
-                    self.ram[(self.i + index) as usize] = self.v[index as usize];
+                self.check_memory(self.i as usize, x as usize + 1)?;
+                for index in 0..=x as usize {
+                    self.ram[self.i as usize + index] = self.v[index];
                 }
+                self.record_write(self.i as usize, x as usize + 1);
 
-                // The original COSMAC VIP machine incremented I by one
-                self.cosmac_increment_i();
-                self.next_instruction();
+                self.quirk_increment_i(x);
+                self.next_instruction()?;
             }
             (0xF, _, 0x6, 0x5) => {
                 // Fx65 - LD Vx, [I]
                 // Read registers V0 through Vx from memory starting at location I.
                 let x: u16 = op_code.get_x();
-                for register in 0..=x {
-                    self.v[register as usize] = self.ram[(self.i + register) as usize];
+                self.check_memory(self.i as usize, x as usize + 1)?;
+                for register in 0..=x as usize {
+                    self.v[register] = self.ram[self.i as usize + register];
                 }
 
-                // The original COSMAC VIP machine incremented I by one
-                self.cosmac_increment_i();
-                self.next_instruction();
+                self.quirk_increment_i(x);
+                self.next_instruction()?;
+            }
+            (0xF, _, 0x7, 0x5) if self.platform.has_schip_instructions() => {
+                // Fx75 - LD R, Vx
+                // Store registers V0 through Vx in the RPL user flags.
+                let x: usize = op_code.get_x() as usize;
+                self.rpl[..=x].copy_from_slice(&self.v[..=x]);
+                self.next_instruction()?;
+            }
+            (0xF, _, 0x8, 0x5) if self.platform.has_schip_instructions() => {
+                // Fx85 - LD Vx, R
+                // Read registers V0 through Vx from the RPL user flags.
+                let x: usize = op_code.get_x() as usize;
+                self.v[..=x].copy_from_slice(&self.rpl[..=x]);
+                self.next_instruction()?;
             }
             _ => {
-                Debugger::dprint(String::from("Instruction not supported by CHIP-8. Advancing to next instruction."), false);
-                self.next_instruction();
+                if self.opcode_policy != OpcodePolicy::Ignore {
+                    return Err(Chip8Error::UnknownOpcode { pc: self.pc, op_code: op_code.get_instruction() });
+                }
+                Debugger::dprint(
+                    format!("Ignoring unknown opcode {:#06X} at {:#05X}", op_code.get_instruction(), self.pc),
+                    false
+                );
+                self.next_instruction()?;
             }
         }
+
+        Ok(())
     }
 
     /// Get an operation code using the program counter
-    fn get_op_code(&self) -> u16 {
-        // Cast is required because u8 can't be indexed by u16
-        let addr: usize = self.pc as usize;
-        (self.ram[addr] as u16) << 8 | (self.ram[addr + 1] as u16)
+    fn get_op_code(&self) -> Result<u16, Chip8Error> {
+        self.read_word(self.pc as usize)
+    }
+
+    /// Run a machine code routine with the hook, which is put back afterwards.
+    /// Without a hook the routine is handled like an unknown opcode.
+    fn call_machine_code(&mut self, address: u16) -> Result<(), Chip8Error> {
+        let mut hook: MachineCodeHook = match self.machine_code_hook.take() {
+            Some(hook) => hook,
+            None if self.opcode_policy == OpcodePolicy::Ignore => {
+                Debugger::dprint(format!("Ignoring machine code routine {:#05X} at {:#05X}", address, self.pc), false);
+                return Ok(());
+            }
+            None => return Err(Chip8Error::MachineCodeRoutine { pc: self.pc, address }),
+        };
+        let result: Result<(), Chip8Error> = hook(self, address);
+        self.machine_code_hook.get_or_insert(hook);
+        result
+    }
+
+    /// Check that `length` bytes starting at `start` are inside memory
+    fn check_memory(&self, start: usize, length: usize) -> Result<(), Chip8Error> {
+        if start + length > self.ram.len() {
+            return Err(Chip8Error::InvalidMemoryAccess { pc: self.pc, address: start.max(self.ram.len()) });
+        }
+        Ok(())
+    }
+
+    /// Remember which memory the current instruction wrote to
+    fn record_write(&mut self, start: usize, length: usize) {
+        self.last_write = Some(start..start + length);
+    }
+
+    /// Read a big-endian 16 bit word from memory
+    fn read_word(&self, addr: usize) -> Result<u16, Chip8Error> {
+        self.check_memory(addr, 2)?;
+        Ok((self.ram[addr] as u16) << 8 | (self.ram[addr + 1] as u16))
+    }
+
+    /// Registers Vx through Vy, in descending order if x is greater than y
+    fn register_range(x: usize, y: usize) -> impl Iterator<Item = usize> {
+        (0..=x.abs_diff(y)).map(move |offset| if x <= y { x + offset } else { x - offset })
     }
 
     /// Increases the program counter by 2 to go to the next program instruction
-    fn next_instruction(&mut self) {
-        self.pc += 2;
+    fn next_instruction(&mut self) -> Result<(), Chip8Error> {
+        self.advance(2)
     }
 
     /// Skip the next instruction in the program counter
-    fn skip_instruction(&mut self) {
-        self.pc += 4;
+    /// The XO-CHIP F000 nnnn instruction is 4 bytes long, so it is skipped as a whole
+    fn skip_instruction(&mut self) -> Result<(), Chip8Error> {
+        if self.platform.has_xochip_instructions() && self.read_word(self.pc as usize + 2).ok() == Some(0xF000) {
+            self.advance(6)
+        } else {
+            self.advance(4)
+        }
+    }
+
+    /// Move the program counter forward, faulting if it would run past the end of the address space
+    fn advance(&mut self, length: u16) -> Result<(), Chip8Error> {
+        self.pc = self.pc
+            .checked_add(length)
+            .ok_or(Chip8Error::InvalidMemoryAccess { pc: self.pc, address: self.pc as usize + length as usize })?;
+        Ok(())
     }
 
     /// Jump to an instruction in the program counter
@@ -507,62 +1039,1082 @@ impl CHIP8 {
     }
 
     /// Push to the CHIP-8 stack
-    fn stack_push(&mut self, value: u16) {
+    fn stack_push(&mut self, value: u16) -> Result<(), Chip8Error> {
+        if self.stack_pointer as usize >= STACK_SIZE {
+            return Err(Chip8Error::StackOverflow { pc: self.pc });
+        }
         self.stack[self.stack_pointer as usize] = value;
         self.stack_pointer += 1;
+        Ok(())
     }
 
     /// Pop from the CHIP-8 stack
-    fn stack_pop(&mut self) -> u16 {
+    fn stack_pop(&mut self) -> Result<u16, Chip8Error> {
+        if self.stack_pointer == 0 {
+            return Err(Chip8Error::StackUnderflow { pc: self.pc });
+        }
         self.stack_pointer -= 1;
         let value: u16 = self.stack[self.stack_pointer as usize];
         self.stack[self.stack_pointer as usize] = 0;
-        value
+        Ok(value)
+    }
+
+    /// Draw a sprite from memory location I at (Vx, Vy) and set VF on collision.
+    /// SUPER-CHIP in high resolution mode sets VF to the number of colliding or clipped rows instead.
+    /// Sprites are `width` pixels wide, which is either 8 or 16 for the SUPER-CHIP Dxy0 sprites.
+    fn draw_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: u16,
+        height: u16
+    ) -> Result<(), Chip8Error> {
+        let display_width: usize = self.display_width();
+        let display_height: usize = self.display_height();
+        let bytes_per_row: u16 = width / 8;
+
+        // Every selected bitplane reads its own sprite data
+        let plane_count: usize = self.planes.count_ones() as usize;
+        self.check_memory(self.i as usize, plane_count * (height * bytes_per_row) as usize)?;
+
+        // The starting position always wraps around the screen
+        let vx: usize = self.v[x] as usize % display_width;
+        let vy: usize = self.v[y] as usize % display_height;
+
+        let mut bit_flipped = 0;
+        // SUPER-CHIP 1.1 counts the rows that collided or were clipped at the bottom in high resolution mode
+        let counts_rows: bool = self.hires && self.platform == Platform::SuperChip;
+        let mut collided_rows: u8 = 0;
+
+        // Each selected bitplane reads its own sprite data, one after the other
+        let mut address: usize = self.i as usize;
+        for plane in [0b01, 0b10] {
+            if self.planes & plane == 0 {
+                continue;
+            }
+
+            for i in 0..height {
+                let row_address: usize = address + (i * bytes_per_row) as usize;
+                let y_coord: usize = vy + i as usize;
+                if y_coord >= display_height && self.quirks.clipping {
+                    collided_rows += (height - i) as u8;
+                    break;
+                }
+
+                let pixels: u16 = if bytes_per_row == 2 {
+                    (self.ram[row_address] as u16) << 8 | self.ram[row_address + 1] as u16
+                } else {
+                    (self.ram[row_address] as u16) << 8
+                };
+
+                let mut row_collided: bool = false;
+                for j in 0..width {
+                    let x_coord: usize = vx + j as usize;
+                    if x_coord >= display_width && self.quirks.clipping {
+                        break;
+                    }
+
+                    if (pixels & (0b1000_0000_0000_0000 >> j)) != 0 {
+                        let pixel_index = (x_coord % display_width) + display_width * (y_coord % display_height);
+                        if self.vram[pixel_index] & plane != 0 {
+                            bit_flipped = 1;
+                            row_collided = true;
+                        }
+                        self.vram[pixel_index] ^= plane;
+                    }
+                }
+                if row_collided {
+                    collided_rows += 1;
+                }
+            }
+
+            address += (height * bytes_per_row) as usize;
+        }
+
+        if counts_rows {
+            self.v[0xF] = collided_rows;
+        } else if bit_flipped == 1 {
+            self.v[0xF] = 1;
+        } else {
+            self.v[0xF] = 0;
+        }
+
+        if self.quirks.display_wait {
+            self.vblank_wait = true;
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record_draw();
+        }
+        Ok(())
+    }
+
+    /// Switch between the low and high resolution modes. Switching clears the display.
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.vram = [0; VRAM_SIZE];
+    }
+
+    /// Copy the selected bitplanes of a source pixel into the pixel at index
+    fn set_plane_pixel(&mut self, index: usize, source: u8) {
+        self.vram[index] = (self.vram[index] & !self.planes) | (source & self.planes);
+    }
+
+    /// Scroll the display down by n pixels
+    fn scroll_down(&mut self, n: usize) {
+        let width: usize = self.display_width();
+        let height: usize = self.display_height();
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let source: u8 = if y >= n { self.vram[x + width * (y - n)] } else { 0 };
+                self.set_plane_pixel(x + width * y, source);
+            }
+        }
     }
 
-    /// Increments I by 1.
+    /// Scroll the display right by n pixels
+    fn scroll_right(&mut self, n: usize) {
+        let width: usize = self.display_width();
+        let height: usize = self.display_height();
+        for y in 0..height {
+            for x in (0..width).rev() {
+                let source: u8 = if x >= n { self.vram[x - n + width * y] } else { 0 };
+                self.set_plane_pixel(x + width * y, source);
+            }
+        }
+    }
+
+    /// Scroll the display left by n pixels
+    fn scroll_left(&mut self, n: usize) {
+        let width: usize = self.display_width();
+        let height: usize = self.display_height();
+        for y in 0..height {
+            for x in 0..width {
+                let source: u8 = if x + n < width { self.vram[x + n + width * y] } else { 0 };
+                self.set_plane_pixel(x + width * y, source);
+            }
+        }
+    }
+
+    /// Increments I by x + 1 unless the load/store quirk is enabled.
     /// The COSMAC VIP did this for the save and load opcodes Fx55 and Fx65 respectively.
-    fn cosmac_increment_i(&mut self) {
-        self.i += 1;
+    fn quirk_increment_i(&mut self, x: u16) {
+        if !self.quirks.load_store {
+            self.i = self.i.wrapping_add(x + 1);
+        }
     }
 
-    /// Clears the VF flag
+    /// Clears the VF flag if the VF reset quirk is enabled.
     /// The COSMAC VIP did this for the bitwise operand opcodes 8xy1, 8xy2 and 8xy3.
-    fn cosmac_clear_vf(&mut self) {
-        self.v[0xF] = 0;
+    fn quirk_clear_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
     }
 
-    /// Sets the register VX to the value of VY
+    /// Sets the register VX to the value of VY unless the shift quirk is enabled.
     /// The COSMAC VIP did this for the bitshift operand opcodes 8xy6 and 8xyE.
-    fn cosmac_set_vx_to_vy(
+    /// CHIP-48 and SUPER-CHIP shift VX in place.
+    fn quirk_set_vx_to_vy(
         &mut self,
         x: usize,
         y: usize
     ) {
-        self.v[x] = self.v[y];
+        if !self.quirks.shift {
+            self.v[x] = self.v[y];
+        }
     }
+}
 
-    /// Check to see if a sprite should be clipped
-    /// The COSMAC VIP clips the sprite if it overflows off screen for the draw opcode Dxyn
-    fn cosmac_sprite_bounds_check(
-        &mut self,
-        v_register: u16,
-        indexer: u16,
-        bounds: u16,
-    ) -> bool {
-        ((v_register + indexer) % bounds) + 1 > (bounds - 1)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::quirks::QuirkProfile;
+
+    /// Create a machine for a quirk profile running the given instructions from 0x200
+    fn machine(profile: QuirkProfile, program: &[u16]) -> CHIP8 {
+        machine_with_quirks(profile.platform(), profile.quirks(), program)
     }
 
-    /// Perform a certain amount of ticks per CPU cycle
-    /// The COSMAC VIP sets the frame limit to 8 ticks per cycle at 60 kHz
-    fn cosmac_cycle_ticks(
-        &mut self,
-        frame_limit: u16
-    ) {
-        for _i in 0..frame_limit {
-            let op_code_value: u16 = self.get_op_code();
-            let op_code: OpCode = OpCode::new(op_code_value);
-            self.run_instruction(&op_code);
+    fn machine_with_quirks(platform: Platform, quirks: Quirks, program: &[u16]) -> CHIP8 {
+        let mut processor: CHIP8 = CHIP8::new(platform, quirks);
+        let rom: Vec<u8> = program.iter().flat_map(|word| word.to_be_bytes()).collect();
+        processor.load_rom_data(&rom).unwrap();
+        processor
+    }
+
+    /// Quirks with every flag off, to toggle one at a time
+    fn no_quirks() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: false,
+            vf_reset: false,
+            clipping: false,
+            jump: false,
+            display_wait: false,
+        }
+    }
+
+    fn run(processor: &mut CHIP8, count: usize) {
+        processor.run_cycles(count).unwrap();
+    }
+
+    /// Number of lit pixels on the display
+    fn lit_pixels(processor: &CHIP8) -> usize {
+        processor.vram.iter().filter(|pixel| **pixel != 0).count()
+    }
+
+    #[test]
+    fn cls_clears_the_display() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x00E0]);
+        processor.vram[0] = 1;
+        processor.vram[64 * 32 - 1] = 1;
+        run(&mut processor, 1);
+        assert_eq!(lit_pixels(&processor), 0);
+        assert_eq!(processor.pc, 0x202);
+    }
+
+    #[test]
+    fn cls_only_clears_the_selected_bitplanes() {
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &[0xF101, 0x00E0]);
+        processor.vram[0] = 0b11;
+        run(&mut processor, 2);
+        assert_eq!(processor.vram[0], 0b10);
+    }
+
+    #[test]
+    fn scroll_down() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0x00C2]);
+        processor.vram[3] = 1;
+        run(&mut processor, 1);
+        assert_eq!(processor.vram[3], 0);
+        assert_eq!(processor.vram[3 + 64 * 2], 1);
+    }
+
+    #[test]
+    fn scroll_right_and_left() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0x00FB, 0x00FC, 0x00FC]);
+        processor.vram[0] = 1;
+        run(&mut processor, 1);
+        assert_eq!(processor.vram[4], 1);
+        run(&mut processor, 1);
+        assert_eq!(processor.vram[0], 1);
+
+        // Pixels scrolled off the edge are lost
+        run(&mut processor, 1);
+        assert_eq!(lit_pixels(&processor), 0);
+    }
+
+    #[test]
+    fn scroll_instructions_need_superchip() {
+        // On the COSMAC VIP 00FB is a call to a machine code routine
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x00FB]);
+        processor.set_opcode_policy(OpcodePolicy::Halt);
+        assert!(matches!(processor.step(), Err(Chip8Error::MachineCodeRoutine { address: 0x0FB, .. })));
+    }
+
+    #[test]
+    fn exit_stops_the_machine() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0x00FD, 0x6001]);
+        run(&mut processor, 2);
+        assert!(processor.has_exited());
+        assert_eq!(processor.pc, 0x200);
+        assert_eq!(processor.v[0], 0);
+    }
+
+    #[test]
+    fn high_and_low_resolution() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0x00FF, 0x00FE]);
+        processor.vram[0] = 1;
+        run(&mut processor, 1);
+        assert_eq!((processor.display_width(), processor.display_height()), (128, 64));
+        assert_eq!(lit_pixels(&processor), 0);
+
+        processor.vram[0] = 1;
+        run(&mut processor, 1);
+        assert_eq!((processor.display_width(), processor.display_height()), (64, 32));
+        assert_eq!(lit_pixels(&processor), 0);
+    }
+
+    #[test]
+    fn call_and_return() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x2206, 0x0000, 0x0000, 0x00EE]);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x206);
+        assert_eq!(processor.stack(), &[0x200]);
+
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x202);
+        assert!(processor.stack().is_empty());
+    }
+
+    #[test]
+    fn return_with_an_empty_stack_faults() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x00EE]);
+        assert!(matches!(processor.step(), Err(Chip8Error::StackUnderflow { pc: 0x200 })));
+    }
+
+    #[test]
+    fn call_with_a_full_stack_faults() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x2200]);
+        run(&mut processor, STACK_SIZE);
+        assert!(matches!(processor.step(), Err(Chip8Error::StackOverflow { pc: 0x200 })));
+        assert_eq!(processor.stack_pointer() as usize, STACK_SIZE);
+    }
+
+    #[test]
+    fn machine_code_routines_follow_the_policy_without_a_hook() {
+        for policy in [OpcodePolicy::Halt, OpcodePolicy::Break] {
+            let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x0123]);
+            processor.set_opcode_policy(policy);
+            assert!(matches!(processor.step(), Err(Chip8Error::MachineCodeRoutine { pc: 0x200, address: 0x123 })));
+            assert_eq!(processor.pc, 0x200);
+        }
+
+        // Skipped by default, like the interpreter has always done
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x0123]);
+        assert_eq!(processor.opcode_policy(), OpcodePolicy::Ignore);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x202);
+    }
+
+    #[test]
+    fn machine_code_routines_run_the_hook() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x0123, 0x0456]);
+        processor.set_machine_code_hook(Some(Box::new(|processor: &mut CHIP8, address: u16| {
+            if address == 0x456 {
+                return Err(Chip8Error::Config(String::from("Unsupported routine")));
+            }
+            processor.write_memory(0x300, &[0xAB])
+        })));
+
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x202);
+        assert_eq!(processor.ram[0x300], 0xAB);
+
+        // The hook is kept after it fails
+        assert!(matches!(processor.step(), Err(Chip8Error::Config(_))));
+        assert!(processor.machine_code_hook.is_some());
+    }
+
+    #[test]
+    fn later_platforms_treat_0nnn_as_unknown() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0x0123]);
+        processor.set_opcode_policy(OpcodePolicy::Halt);
+        assert!(matches!(processor.step(), Err(Chip8Error::UnknownOpcode { pc: 0x200, op_code: 0x0123 })));
+    }
+
+    #[test]
+    fn unknown_opcode_policy() {
+        for policy in [OpcodePolicy::Halt, OpcodePolicy::Break] {
+            let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x5001]);
+            processor.set_opcode_policy(policy);
+            assert!(matches!(processor.step(), Err(Chip8Error::UnknownOpcode { pc: 0x200, op_code: 0x5001 })));
+            assert_eq!(processor.pc, 0x200);
+        }
+
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x5001, 0x0000]);
+        processor.set_opcode_policy(OpcodePolicy::Ignore);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x202);
+
+        // 0000 is never a machine code routine, it is usually a jump into empty memory
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x204);
+    }
+
+    #[test]
+    fn jump() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x1ABC]);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0xABC);
+    }
+
+    #[test]
+    fn fetching_past_the_end_of_memory_faults() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x1FFF]);
+        run(&mut processor, 1);
+        assert!(matches!(processor.step(), Err(Chip8Error::InvalidMemoryAccess { pc: 0xFFF, address: 0x1000 })));
+    }
+
+    #[test]
+    fn running_off_the_end_of_64k_memory_faults() {
+        // JP 0xFFE, then LD V0, 0 at every address up to the last word of memory
+        let mut program: Vec<u16> = vec![0x6000; (0x10000 - 0x200) / 2];
+        program[0] = 0x1FFE;
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &program);
+        run(&mut processor, 1 + (0xFFFE - 0xFFE) / 2);
+        assert_eq!(processor.pc, 0xFFFE);
+        assert!(matches!(processor.step(), Err(Chip8Error::InvalidMemoryAccess { pc: 0xFFFE, address: 0x10000 })));
+
+        // Skipping the last instruction in memory
+        processor.pc = 0xFFFC;
+        processor.ram[0xFFFC..].copy_from_slice(&[0x30, 0x00, 0x00, 0xE0]);
+        assert!(matches!(processor.step(), Err(Chip8Error::InvalidMemoryAccess { pc: 0xFFFC, address: 0x10000 })));
+    }
+
+    #[test]
+    fn skip_if_equal_to_byte() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x3012, 0x0000, 0x3013]);
+        processor.v[0] = 0x12;
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x204);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x206);
+    }
+
+    #[test]
+    fn skip_if_not_equal_to_byte() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x4013, 0x0000, 0x4012]);
+        processor.v[0] = 0x12;
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x204);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x206);
+    }
+
+    #[test]
+    fn skip_if_registers_equal() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x5010, 0x0000, 0x5020]);
+        processor.v[0] = 7;
+        processor.v[1] = 7;
+        processor.v[2] = 8;
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x204);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x206);
+    }
+
+    #[test]
+    fn skip_if_registers_not_equal() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x9020, 0x0000, 0x9010]);
+        processor.v[0] = 7;
+        processor.v[1] = 7;
+        processor.v[2] = 8;
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x204);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x206);
+    }
+
+    #[test]
+    fn skips_step_over_long_loads_on_xochip() {
+        let program: [u16; 3] = [0x3000, 0xF000, 0x1234];
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &program);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x206);
+
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &program);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x204);
+    }
+
+    #[test]
+    fn store_register_range() {
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &[0x5132, 0x5312]);
+        processor.v[1..=3].copy_from_slice(&[1, 2, 3]);
+        processor.i = 0x300;
+        run(&mut processor, 1);
+        assert_eq!(&processor.ram[0x300..0x303], &[1, 2, 3]);
+        assert_eq!(processor.i, 0x300);
+        assert_eq!(processor.last_write(), Some(0x300..0x303));
+
+        // A descending range stores the registers in reverse order
+        run(&mut processor, 1);
+        assert_eq!(&processor.ram[0x300..0x303], &[3, 2, 1]);
+    }
+
+    #[test]
+    fn load_register_range() {
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &[0x5133, 0x5313]);
+        processor.write_memory(0x300, &[1, 2, 3]).unwrap();
+        processor.i = 0x300;
+        run(&mut processor, 1);
+        assert_eq!(&processor.v[1..=3], &[1, 2, 3]);
+        assert_eq!(processor.i, 0x300);
+
+        run(&mut processor, 1);
+        assert_eq!(&processor.v[1..=3], &[3, 2, 1]);
+    }
+
+    #[test]
+    fn register_ranges_need_xochip() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0x5132]);
+        processor.set_opcode_policy(OpcodePolicy::Halt);
+        assert!(matches!(processor.step(), Err(Chip8Error::UnknownOpcode { .. })));
+    }
+
+    #[test]
+    fn load_byte() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x6A42]);
+        run(&mut processor, 1);
+        assert_eq!(processor.v[0xA], 0x42);
+    }
+
+    #[test]
+    fn add_byte_wraps_without_a_carry() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x7002]);
+        processor.v[0] = 0xFF;
+        run(&mut processor, 1);
+        assert_eq!(processor.v[0], 0x01);
+        assert_eq!(processor.v[0xF], 0);
+    }
+
+    #[test]
+    fn load_register() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x8010]);
+        processor.v[1] = 0x42;
+        run(&mut processor, 1);
+        assert_eq!(processor.v[0], 0x42);
+    }
+
+    #[test]
+    fn logical_operations() {
+        for (op_code, expected) in [(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)] {
+            let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, no_quirks(), &[op_code]);
+            processor.v[0] = 0b1100;
+            processor.v[1] = 0b1010;
+            processor.v[0xF] = 5;
+            run(&mut processor, 1);
+            assert_eq!(processor.v[0], expected, "{:#06X}", op_code);
+            assert_eq!(processor.v[0xF], 5, "{:#06X}", op_code);
         }
     }
+
+    #[test]
+    fn logical_operations_reset_vf_with_quirk() {
+        let quirks: Quirks = Quirks { vf_reset: true, ..no_quirks() };
+        for op_code in [0x8011, 0x8012, 0x8013] {
+            let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, quirks, &[op_code]);
+            processor.v[0xF] = 5;
+            run(&mut processor, 1);
+            assert_eq!(processor.v[0xF], 0, "{:#06X}", op_code);
+        }
+    }
+
+    #[test]
+    fn add_registers_sets_carry() {
+        for (vx, vy, sum, carry) in [(0x10, 0x20, 0x30, 0), (0xFF, 0x02, 0x01, 1), (0xFF, 0x01, 0x00, 1)] {
+            let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x8014]);
+            processor.v[0] = vx;
+            processor.v[1] = vy;
+            run(&mut processor, 1);
+            assert_eq!((processor.v[0], processor.v[0xF]), (sum, carry), "{:#04X} + {:#04X}", vx, vy);
+        }
+    }
+
+    #[test]
+    fn subtract_registers_sets_not_borrow() {
+        for (vx, vy, difference, not_borrow) in [(5, 3, 2, 1), (3, 5, 0xFE, 0), (5, 5, 0, 1), (0, 0, 0, 1)] {
+            let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x8015]);
+            processor.v[0] = vx;
+            processor.v[1] = vy;
+            run(&mut processor, 1);
+            assert_eq!((processor.v[0], processor.v[0xF]), (difference, not_borrow), "{} - {}", vx, vy);
+        }
+    }
+
+    #[test]
+    fn reverse_subtract_registers_sets_not_borrow() {
+        for (vx, vy, difference, not_borrow) in [(3, 5, 2, 1), (5, 3, 0xFE, 0), (5, 5, 0, 1), (0, 5, 5, 1), (0, 0, 0, 1)] {
+            let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x8017]);
+            processor.v[0] = vx;
+            processor.v[1] = vy;
+            run(&mut processor, 1);
+            assert_eq!((processor.v[0], processor.v[0xF]), (difference, not_borrow), "{} - {}", vy, vx);
+        }
+    }
+
+    #[test]
+    fn arithmetic_flag_wins_over_the_result_in_vf() {
+        // 8F14, 8F15, 8F17, 8F16 and 8F1E with VF as the destination
+        for (op_code, vf, vy, flag) in [
+            (0x8F14, 0xFF, 0x01, 1),
+            (0x8F15, 0x01, 0x02, 0),
+            (0x8F17, 0x03, 0x05, 1),
+            (0x8F16, 0x00, 0x03, 1),
+            (0x8F1E, 0x00, 0x40, 0),
+        ] {
+            let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[op_code]);
+            processor.v[0xF] = vf;
+            processor.v[1] = vy;
+            run(&mut processor, 1);
+            assert_eq!(processor.v[0xF], flag, "{:#06X}", op_code);
+        }
+    }
+
+    #[test]
+    fn shift_right() {
+        // Without the shift quirk Vy is shifted into Vx
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, no_quirks(), &[0x8016]);
+        processor.v[0] = 0b1000;
+        processor.v[1] = 0b0011;
+        run(&mut processor, 1);
+        assert_eq!((processor.v[0], processor.v[0xF]), (0b0001, 1));
+
+        let quirks: Quirks = Quirks { shift: true, ..no_quirks() };
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, quirks, &[0x8016]);
+        processor.v[0] = 0b1000;
+        processor.v[1] = 0b0011;
+        run(&mut processor, 1);
+        assert_eq!((processor.v[0], processor.v[0xF]), (0b0100, 0));
+    }
+
+    #[test]
+    fn shift_left() {
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, no_quirks(), &[0x801E]);
+        processor.v[0] = 0x01;
+        processor.v[1] = 0x81;
+        run(&mut processor, 1);
+        assert_eq!((processor.v[0], processor.v[0xF]), (0x02, 1));
+
+        let quirks: Quirks = Quirks { shift: true, ..no_quirks() };
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, quirks, &[0x801E]);
+        processor.v[0] = 0x01;
+        processor.v[1] = 0x81;
+        run(&mut processor, 1);
+        assert_eq!((processor.v[0], processor.v[0xF]), (0x02, 0));
+    }
+
+    #[test]
+    fn load_index() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xA123]);
+        run(&mut processor, 1);
+        assert_eq!(processor.i, 0x123);
+    }
+
+    #[test]
+    fn jump_with_offset() {
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, no_quirks(), &[0xB210]);
+        processor.v[0] = 4;
+        processor.v[2] = 8;
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x214);
+
+        // With the jump quirk the offset comes from Vx
+        let quirks: Quirks = Quirks { jump: true, ..no_quirks() };
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, quirks, &[0xB210]);
+        processor.v[0] = 4;
+        processor.v[2] = 8;
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x218);
+    }
+
+    #[test]
+    fn random_byte_is_masked() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xC00F, 0xC100]);
+        processor.set_rng(Chip8Rng::new(1));
+        run(&mut processor, 2);
+        assert_eq!(processor.v[0], Chip8Rng::new(1).next_byte() & 0x0F);
+        assert_eq!(processor.v[1], 0);
+        assert_eq!(processor.rng().draws(), 2);
+    }
+
+    #[test]
+    fn draw_sets_collision() {
+        // Digit 0 of the font starts with a row of 4 pixels
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xD011, 0xD011]);
+        run(&mut processor, 1);
+        assert_eq!(&processor.vram[..5], &[1, 1, 1, 1, 0]);
+        assert_eq!(processor.v[0xF], 0);
+
+        run(&mut processor, 1);
+        assert_eq!(lit_pixels(&processor), 0);
+        assert_eq!(processor.v[0xF], 1);
+    }
+
+    #[test]
+    fn draw_wraps_the_starting_position() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xD011]);
+        processor.v[0] = 64 + 2;
+        processor.v[1] = 32 + 1;
+        run(&mut processor, 1);
+        assert_eq!(processor.vram[2 + 64], 1);
+        assert_eq!(lit_pixels(&processor), 4);
+    }
+
+    #[test]
+    fn draw_clips_at_the_edges_with_quirk() {
+        let quirks: Quirks = Quirks { clipping: true, ..no_quirks() };
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, quirks, &[0xD015]);
+        processor.v[0] = 62;
+        processor.v[1] = 30;
+        run(&mut processor, 1);
+        assert_eq!(&processor.vram[62 + 64 * 30..64 * 31], &[1, 1]);
+        assert_eq!(processor.vram[64 * 30], 0);
+        assert_eq!(processor.vram[62], 0);
+        assert_eq!(lit_pixels(&processor), 3);
+    }
+
+    #[test]
+    fn draw_wraps_at_the_edges_without_quirk() {
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, no_quirks(), &[0xD015]);
+        processor.v[0] = 62;
+        processor.v[1] = 30;
+        run(&mut processor, 1);
+        assert_eq!(&processor.vram[62 + 64 * 30..64 * 31], &[1, 1]);
+        assert_eq!(&processor.vram[64 * 30..64 * 30 + 3], &[1, 1, 0]);
+        assert_eq!(processor.vram[62], 1);
+        assert_eq!(lit_pixels(&processor), 14);
+    }
+
+    #[test]
+    fn draw_waits_for_vblank_with_quirk() {
+        let quirks: Quirks = Quirks { display_wait: true, ..no_quirks() };
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, quirks, &[0xD011, 0xD011]);
+        // The frame ends after the first draw
+        processor.run_frame().unwrap();
+        assert!(processor.is_waiting_for_vblank());
+        assert_eq!(processor.pc, 0x202);
+
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, no_quirks(), &[0xD011]);
+        run(&mut processor, 1);
+        assert!(!processor.is_waiting_for_vblank());
+    }
+
+    #[test]
+    fn draw_big_sprite() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0x00FF, 0xD010]);
+        processor.write_memory(0x300, &[0xFF; 32]).unwrap();
+        processor.i = 0x300;
+        run(&mut processor, 2);
+        assert_eq!(lit_pixels(&processor), 16 * 16);
+        assert_eq!(processor.vram[15 + 128 * 15], 1);
+    }
+
+    #[test]
+    fn draw_counts_colliding_rows_in_superchip_hires() {
+        // Two rows of a 4 row sprite collide and the last row is clipped at the bottom
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0x00FF, 0xD014, 0xD014]);
+        processor.write_memory(0x300, &[0x80, 0x40, 0x00, 0x10]).unwrap();
+        processor.i = 0x300;
+        processor.v[1] = 61;
+        run(&mut processor, 2);
+        assert_eq!(processor.v[0xF], 1);
+
+        processor.write_memory(0x300, &[0x80, 0x40, 0x20, 0x10]).unwrap();
+        run(&mut processor, 1);
+        assert_eq!(processor.v[0xF], 3);
+
+        // Low resolution mode and XO-CHIP keep the collision flag
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0xD012, 0xD012]);
+        processor.write_memory(0x300, &[0x80, 0x40]).unwrap();
+        processor.i = 0x300;
+        run(&mut processor, 2);
+        assert_eq!(processor.v[0xF], 1);
+
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &[0x00FF, 0xD012, 0xD012]);
+        processor.write_memory(0x300, &[0x80, 0x40]).unwrap();
+        processor.i = 0x300;
+        run(&mut processor, 3);
+        assert_eq!(processor.v[0xF], 1);
+    }
+
+    #[test]
+    fn draw_reads_one_sprite_per_bitplane() {
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &[0xF301, 0xD011]);
+        processor.write_memory(0x300, &[0xF0, 0x3C]).unwrap();
+        processor.i = 0x300;
+        run(&mut processor, 2);
+        assert_eq!(&processor.vram[..8], &[1, 1, 3, 3, 2, 2, 0, 0]);
+    }
+
+    #[test]
+    fn draw_past_the_end_of_memory_faults() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xD01F]);
+        processor.i = 0xFFA;
+        assert!(matches!(processor.step(), Err(Chip8Error::InvalidMemoryAccess { pc: 0x200, .. })));
+    }
+
+    #[test]
+    fn skip_if_key_pressed() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xE09E, 0x0000, 0xE09E]);
+        // Only the low nibble of Vx selects the key
+        processor.v[0] = 0x15;
+        processor.press_key(5, true);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x204);
+
+        processor.press_key(5, false);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x206);
+    }
+
+    #[test]
+    fn skip_if_key_not_pressed() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xE0A1, 0x0000, 0xE0A1]);
+        processor.v[0] = 0x15;
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x204);
+
+        processor.press_key(5, true);
+        run(&mut processor, 1);
+        assert_eq!(processor.pc, 0x206);
+    }
+
+    #[test]
+    fn load_long_index() {
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &[0xF000, 0xABCD]);
+        run(&mut processor, 1);
+        assert_eq!(processor.i, 0xABCD);
+        assert_eq!(processor.pc, 0x204);
+    }
+
+    #[test]
+    fn select_bitplanes() {
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &[0xF201]);
+        run(&mut processor, 1);
+        assert_eq!(processor.planes, 0b10);
+    }
+
+    #[test]
+    fn load_audio_pattern_and_pitch() {
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &[0xF002, 0xF03A]);
+        processor.write_memory(0x300, &[0xAA; AUDIO_PATTERN_SIZE]).unwrap();
+        processor.i = 0x300;
+        processor.v[0] = 112;
+        run(&mut processor, 2);
+        assert_eq!(processor.audio_pattern(), Some([0xAA; AUDIO_PATTERN_SIZE]));
+        assert_eq!(processor.audio_playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn timers() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xF015, 0xF118, 0xF207]);
+        processor.v[0] = 10;
+        processor.v[1] = 20;
+        run(&mut processor, 2);
+        processor.timers_tick();
+        run(&mut processor, 1);
+        assert_eq!(processor.v[2], 9);
+        assert_eq!(processor.sound_timer, 19);
+    }
+
+    #[test]
+    fn wait_for_key() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xF00A]);
+        run(&mut processor, 3);
+        assert_eq!(processor.pc, 0x200);
+
+        processor.press_key(7, true);
+        run(&mut processor, 1);
+        assert_eq!(processor.v[0], 7);
+        assert_eq!(processor.pc, 0x202);
+    }
+
+    #[test]
+    fn add_to_index() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xF01E]);
+        processor.i = 0x123;
+        processor.v[0] = 0x10;
+        run(&mut processor, 1);
+        assert_eq!(processor.i, 0x133);
+    }
+
+    #[test]
+    fn add_to_index_overflow() {
+        // I is allowed past the end of memory without touching VF
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xF01E, 0xF01E]);
+        processor.i = 0xFFF;
+        processor.v[0] = 1;
+        run(&mut processor, 1);
+        assert_eq!(processor.i, 0x1000);
+        assert_eq!(processor.v[0xF], 0);
+
+        // and wraps around at 16 bits
+        processor.i = 0xFFFF;
+        run(&mut processor, 1);
+        assert_eq!(processor.i, 0x0000);
+    }
+
+    #[test]
+    fn font_digit_location() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xF029]);
+        // Only the low nibble of Vx selects the digit
+        processor.v[0] = 0x1A;
+        run(&mut processor, 1);
+        assert_eq!(processor.i, 0xA * SPRITE_LENGTH as u16);
+        assert_eq!(&processor.ram[processor.i as usize..processor.i as usize + 5], &FONT_SET[50..55]);
+    }
+
+    #[test]
+    fn big_font_digit_location() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0xF030]);
+        processor.v[0] = 2;
+        run(&mut processor, 1);
+        assert_eq!(processor.i as usize, BIG_FONT_START + 2 * BIG_SPRITE_LENGTH as usize);
+    }
+
+    #[test]
+    fn binary_coded_decimal() {
+        for (value, digits) in [(0, [0, 0, 0]), (7, [0, 0, 7]), (42, [0, 4, 2]), (137, [1, 3, 7]), (255, [2, 5, 5])] {
+            let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xF033]);
+            processor.v[0] = value;
+            processor.i = 0x300;
+            run(&mut processor, 1);
+            assert_eq!(&processor.ram[0x300..0x303], &digits, "{}", value);
+            assert_eq!(processor.i, 0x300);
+        }
+    }
+
+    #[test]
+    fn binary_coded_decimal_past_the_end_of_memory_faults() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xF033]);
+        processor.i = 0xFFE;
+        assert!(matches!(processor.step(), Err(Chip8Error::InvalidMemoryAccess { pc: 0x200, address: 0x1000 })));
+        assert_eq!(processor.pc, 0x200);
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, no_quirks(), &[0xF255, 0xA300, 0xF265]);
+        processor.v[..3].copy_from_slice(&[1, 2, 3]);
+        processor.i = 0x300;
+        run(&mut processor, 1);
+        assert_eq!(&processor.ram[0x300..0x304], &[1, 2, 3, 0]);
+        // Without the load/store quirk I is incremented by x + 1
+        assert_eq!(processor.i, 0x303);
+
+        processor.v[..3].copy_from_slice(&[0, 0, 0]);
+        run(&mut processor, 2);
+        assert_eq!(&processor.v[..4], &[1, 2, 3, 0]);
+        assert_eq!(processor.i, 0x303);
+    }
+
+    #[test]
+    fn store_and_load_registers_leave_index_with_quirk() {
+        let quirks: Quirks = Quirks { load_store: true, ..no_quirks() };
+        let mut processor: CHIP8 = machine_with_quirks(Platform::Chip8, quirks, &[0xF255, 0xF265]);
+        processor.i = 0x300;
+        run(&mut processor, 2);
+        assert_eq!(processor.i, 0x300);
+    }
+
+    #[test]
+    fn store_and_load_registers_advance_index_on_chip48() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Chip48, &[0xF255, 0xF365]);
+        processor.v[..3].copy_from_slice(&[1, 2, 3]);
+        processor.i = 0x300;
+        run(&mut processor, 1);
+        assert_eq!(&processor.ram[0x300..0x304], &[1, 2, 3, 0]);
+        assert_eq!(processor.i, 0x303);
+
+        processor.ram[0x303..0x307].copy_from_slice(&[4, 5, 6, 7]);
+        run(&mut processor, 1);
+        assert_eq!(&processor.v[..4], &[4, 5, 6, 7]);
+        assert_eq!(processor.i, 0x307);
+    }
+
+    #[test]
+    fn store_registers_past_the_end_of_memory_faults() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xFF55]);
+        processor.i = 0xFF8;
+        assert!(matches!(processor.step(), Err(Chip8Error::InvalidMemoryAccess { .. })));
+        assert_eq!(processor.i, 0xFF8);
+    }
+
+    #[test]
+    fn store_and_load_user_flags() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Schip11, &[0xF275, 0x6000, 0xF285]);
+        processor.v[..3].copy_from_slice(&[1, 2, 3]);
+        run(&mut processor, 2);
+        assert_eq!(processor.v[0], 0);
+        run(&mut processor, 1);
+        assert_eq!(&processor.v[..4], &[1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn profiling_counts_instructions_draws_and_key_waits() {
+        let mut processor: CHIP8 = machine(QuirkProfile::Modern, &[0xD011, 0xD011, 0xF00A]);
+        processor.start_profiling();
+        processor.run_frame().unwrap();
+
+        // Two draws, then Fx0A waits for the rest of the frame
+        let profile: &Profile = processor.profile().unwrap();
+        assert_eq!(profile.instructions(), DEFAULT_INSTRUCTIONS_PER_FRAME as u64);
+        assert_eq!(profile.frames(), 1);
+        assert_eq!(profile.executions_at(0x204), 6);
+        assert_eq!(profile.executions_of_class(0xD), 2);
+        assert_eq!(profile.draws(), 2);
+        assert_eq!(profile.max_draws_per_frame(), 2);
+        assert_eq!(profile.key_wait_frames(), 1);
+        assert_eq!(profile.hot_spots()[0], (0x204, 6));
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &[
+            0x6005, // LD V0, 5
+            0xF015, // LD DT, V0
+            0xF018, // LD ST, V0
+            0xC1FF, // RND V1, 0xFF
+            0xF201, // PLANE 2
+            0xA200, // LD I, 0x200
+            0x2210, // CALL 0x210
+            0x120E, // JP 0x20E
+            0xD235, // DRW V2, V3, 5
+            0x1212, // JP 0x212
+        ]);
+        processor.set_rng(Chip8Rng::new(42));
+        processor.press_key(0xA, true);
+        run(&mut processor, 9);
+
+        let state: Vec<u8> = processor.save_state();
+        let mut restored: CHIP8 = machine(QuirkProfile::CosmacVip, &[]);
+        restored.load_state(&state).unwrap();
+
+        assert_eq!(restored.platform(), Platform::XoChip);
+        assert_eq!(restored.quirks, QuirkProfile::XoChip.quirks());
+        assert_eq!(restored.v, processor.v);
+        assert_eq!(restored.i, 0x200);
+        assert_eq!(restored.pc, 0x212);
+        assert_eq!(restored.stack(), processor.stack());
+        assert_eq!(restored.stack_pointer(), 1);
+        assert_eq!(restored.delay_timer, 5);
+        assert_eq!(restored.sound_timer, 5);
+        assert_eq!(restored.planes, 2);
+        assert_eq!(restored.vram[..], processor.vram[..]);
+        assert!(restored.vram.contains(&2));
+        assert_eq!(restored.ram, processor.ram);
+        assert!(restored.keys[0xA]);
+        assert_eq!(restored.rng().draws(), 1);
+        assert_eq!(restored.rng().word_position(), processor.rng().word_position());
+        assert_eq!(restored.save_state(), state);
+
+        // Both machines draw the same random numbers from here on
+        assert_eq!(restored.rng.next_byte(), processor.rng.next_byte());
+    }
+
+    #[test]
+    fn invalid_save_states_are_rejected() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x6001]);
+        let state: Vec<u8> = processor.save_state();
+        run(&mut processor, 1);
+
+        let mut wrong_magic: Vec<u8> = state.clone();
+        wrong_magic[0] = b'X';
+        let mut wrong_version: Vec<u8> = state.clone();
+        wrong_version[4] = state::STATE_VERSION + 1;
+        let mut trailing_data: Vec<u8> = state.clone();
+        trailing_data.push(0);
+
+        let invalid: Vec<&[u8]> = vec![&[], &state[..3], &state[..state.len() - 1], &wrong_magic, &wrong_version, &trailing_data];
+        for data in invalid {
+            assert!(matches!(processor.load_state(data), Err(Chip8Error::InvalidState(_))));
+        }
+        // Every truncation fails rather than panicking
+        for length in 0..state.len() {
+            assert!(processor.load_state(&state[..length]).is_err());
+        }
+
+        // The machine is left as it was
+        assert_eq!(processor.v[0], 1);
+        assert_eq!(processor.pc, 0x202);
+    }
+
+    #[test]
+    fn rom_too_large() {
+        let mut processor: CHIP8 = CHIP8::new(Platform::Chip8, Quirks::default());
+        let rom: Vec<u8> = vec![0; 0x1000 - PROGRAM_START + 1];
+        assert!(matches!(processor.load_rom_data(&rom), Err(Chip8Error::RomTooLarge { capacity: 0xE00, .. })));
+    }
 }
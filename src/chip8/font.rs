@@ -1,3 +1,4 @@
+// Low resolution font, each character is 4x5 pixels
 pub const FONT_SET: [u8; 80] = [
     // 0
     0xF0,
@@ -95,4 +96,184 @@ pub const FONT_SET: [u8; 80] = [
     0xF0,
     0x80,
     0x80
+];
+
+// SUPER-CHIP high resolution font, each character is 8x10 pixels
+pub const BIG_FONT_SET: [u8; 160] = [
+    // 0
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    // 1
+    0x18,
+    0x78,
+    0x78,
+    0x18,
+    0x18,
+    0x18,
+    0x18,
+    0x18,
+    0xFF,
+    0xFF,
+    // 2
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    // 3
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    // 4
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0x03,
+    0x03,
+    // 5
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    // 6
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    // 7
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0x06,
+    0x0C,
+    0x18,
+    0x18,
+    0x18,
+    0x18,
+    // 8
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    // 9
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    // A
+    0x7E,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xC3,
+    // B
+    0xFC,
+    0xFC,
+    0xC3,
+    0xC3,
+    0xFC,
+    0xFC,
+    0xC3,
+    0xC3,
+    0xFC,
+    0xFC,
+    // C
+    0x3C,
+    0xFF,
+    0xC3,
+    0xC0,
+    0xC0,
+    0xC0,
+    0xC0,
+    0xC3,
+    0xFF,
+    0x3C,
+    // D
+    0xFC,
+    0xFE,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xFE,
+    0xFC,
+    // E
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    // F
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xC0,
+    0xC0
 ];
\ No newline at end of file
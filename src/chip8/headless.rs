@@ -0,0 +1,164 @@
+use std::fmt;
+
+use crate::error::Chip8Error;
+use super::cpu::CHIP8;
+
+// Characters a screenshot uses for each combination of bitplanes
+const PIXEL_CHARACTERS: [char; 4] = ['.', '#', '+', '@'];
+
+/// A key pressed or released at the start of a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: usize,
+    pub key: u8,
+    pub is_pressed: bool,
+}
+
+/// Runs a machine without a window or sound, feeding it scripted key input.
+/// Used by the `--headless` frontend and the integration tests.
+pub struct HeadlessRunner {
+    processor: CHIP8,
+    key_events: Vec<KeyEvent>,
+    frame: usize,
+}
+
+impl HeadlessRunner {
+    pub fn new(processor: CHIP8) -> Self {
+        HeadlessRunner {
+            processor,
+            key_events: Vec::new(),
+            frame: 0,
+        }
+    }
+
+    pub fn processor(&self) -> &CHIP8 {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut CHIP8 {
+        &mut self.processor
+    }
+
+    /// Number of frames run so far
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Press a key at the start of a frame
+    pub fn press_key(&mut self, frame: usize, key: u8) {
+        self.key_events.push(KeyEvent { frame, key, is_pressed: true });
+    }
+
+    /// Release a key at the start of a frame
+    pub fn release_key(&mut self, frame: usize, key: u8) {
+        self.key_events.push(KeyEvent { frame, key, is_pressed: false });
+    }
+
+    /// Press a key at the start of a frame and release it `frames_held` frames later
+    pub fn tap_key(&mut self, frame: usize, key: u8, frames_held: usize) {
+        self.press_key(frame, key);
+        self.release_key(frame + frames_held, key);
+    }
+
+    /// Run up to `frames` frames, stopping early if the program exits.
+    /// On a fault the machine is left at the faulting instruction.
+    pub fn run(&mut self, frames: usize) -> Result<(), Chip8Error> {
+        for _i in 0..frames {
+            if self.processor.has_exited() {
+                break;
+            }
+
+            for event in self.key_events.iter().filter(|event| event.frame == self.frame) {
+                self.processor.press_key((event.key & 0xF) as usize, event.is_pressed);
+            }
+            self.processor.run_frame()?;
+            self.frame += 1;
+        }
+        Ok(())
+    }
+
+    /// Capture the display in its current resolution
+    pub fn screenshot(&self) -> Screenshot {
+        Screenshot::from_processor(&self.processor)
+    }
+}
+
+/// The display of a machine, stored as text so reference images can be read and edited by hand.
+/// Every row is a line with one character per pixel: `.` when off, `#` for the first bitplane,
+///     `+` for the second bitplane and `@` for both.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screenshot {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn from_processor(processor: &CHIP8) -> Self {
+        let width: usize = processor.display_width();
+        let height: usize = processor.display_height();
+        Screenshot {
+            width,
+            height,
+            pixels: processor.vram[..width * height].iter().map(|pixel| pixel & 0b11).collect(),
+        }
+    }
+
+    /// Parse a screenshot from its text form, ignoring trailing whitespace
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let rows: Vec<&str> = text.trim_end().lines().map(str::trim_end).collect();
+        let width: usize = rows.first().map(|row| row.chars().count()).unwrap_or(0);
+
+        let mut pixels: Vec<u8> = Vec::with_capacity(width * rows.len());
+        for (line, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(format!("Row {} is not {} pixels wide", line + 1, width));
+            }
+            for character in row.chars() {
+                let pixel: usize = PIXEL_CHARACTERS
+                    .iter()
+                    .position(|known| *known == character)
+                    .ok_or_else(|| format!("Unexpected character {:?} on row {}", character, line + 1))?;
+                pixels.push(pixel as u8);
+            }
+        }
+
+        Ok(Screenshot {
+            width,
+            height: rows.len(),
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Number of pixels that differ from another screenshot of the same size.
+    /// Screenshots of different sizes differ in every pixel.
+    pub fn difference(&self, other: &Screenshot) -> usize {
+        if self.width != other.width || self.height != other.height {
+            return self.pixels.len().max(other.pixels.len());
+        }
+
+        self.pixels
+            .iter()
+            .zip(&other.pixels)
+            .filter(|(pixel, other_pixel)| pixel != other_pixel)
+            .count()
+    }
+}
+
+impl fmt::Display for Screenshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.pixels.chunks(self.width.max(1)) {
+            let line: String = row.iter().map(|pixel| PIXEL_CHARACTERS[*pixel as usize]).collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
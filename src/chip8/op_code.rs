@@ -45,7 +45,7 @@ impl OpCode {
             ((self.op_code & 0xF000) >> 12) as u8,
             ((self.op_code & 0x0F00) >> 8) as u8,
             ((self.op_code & 0x00F0) >> 4) as u8,
-            ((self.op_code & 0x000F)) as u8,
+            (self.op_code & 0x000F) as u8,
         );
 
         op_code_split
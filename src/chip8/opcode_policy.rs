@@ -0,0 +1,44 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// What the interpreter does when it meets an instruction the platform doesn't support
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OpcodePolicy {
+    /// Skip the instruction and carry on, like most interpreters do
+    #[default]
+    Ignore,
+    /// Stop the machine with a fault showing the instruction and where it is
+    Halt,
+    /// Stop the machine with a fault and open the debugger on the instruction
+    Break,
+}
+
+impl OpcodePolicy {
+    /// Get the name used to select this policy
+    pub fn name(&self) -> &'static str {
+        match self {
+            OpcodePolicy::Ignore => "ignore",
+            OpcodePolicy::Halt => "halt",
+            OpcodePolicy::Break => "break",
+        }
+    }
+}
+
+impl fmt::Display for OpcodePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OpcodePolicy {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ignore" | "skip" => Ok(OpcodePolicy::Ignore),
+            "halt" | "stop" => Ok(OpcodePolicy::Halt),
+            "break" | "debug" => Ok(OpcodePolicy::Break),
+            _ => Err("Unknown opcode policy. Expected one of: ignore, halt, break"),
+        }
+    }
+}
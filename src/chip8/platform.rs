@@ -0,0 +1,33 @@
+/// The instruction set and memory layout the interpreter emulates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// The original CHIP-8 instruction set with 4 KiB of memory
+    #[default]
+    Chip8,
+
+    /// CHIP-8 plus the SUPER-CHIP 1.1 instructions and the 128x64 high resolution mode
+    SuperChip,
+
+    /// SUPER-CHIP plus the XO-CHIP instructions, bitplanes and 64 KiB of memory
+    XoChip,
+}
+
+impl Platform {
+    /// Amount of addressable memory in bytes
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    /// Whether the SUPER-CHIP instructions are available
+    pub fn has_schip_instructions(&self) -> bool {
+        matches!(self, Platform::SuperChip | Platform::XoChip)
+    }
+
+    /// Whether the XO-CHIP instructions are available
+    pub fn has_xochip_instructions(&self) -> bool {
+        matches!(self, Platform::XoChip)
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::platform::Platform;
+
+/// Behaviours that differ between CHIP-8 interpreters.
+/// Each flag describes what the interpreter does when it is set to true.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6 and 8xyE shift Vx in place and ignore Vy.
+    /// The COSMAC VIP copied Vy into Vx before shifting.
+    pub shift: bool,
+
+    /// Fx55 and Fx65 leave I untouched.
+    /// The COSMAC VIP incremented I by x + 1.
+    pub load_store: bool,
+
+    /// 8xy1, 8xy2 and 8xy3 reset VF to 0.
+    pub vf_reset: bool,
+
+    /// Dxyn clips sprites at the edge of the screen instead of wrapping them around.
+    pub clipping: bool,
+
+    /// Bnnn jumps to xnn + Vx instead of nnn + V0.
+    pub jump: bool,
+
+    /// Dxyn waits for the vertical blank, which limits drawing to once per frame.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift: false,
+        load_store: false,
+        vf_reset: true,
+        clipping: true,
+        jump: false,
+        display_wait: true,
+    };
+
+    /// The CHIP-48 interpreter for the HP-48 calculators
+    pub const CHIP_48: Quirks = Quirks {
+        shift: true,
+        load_store: false,
+        vf_reset: false,
+        clipping: true,
+        jump: true,
+        display_wait: false,
+    };
+
+    /// The SUPER-CHIP 1.1 interpreter for the HP-48 calculators
+    pub const SCHIP_1_1: Quirks = Quirks {
+        shift: true,
+        load_store: true,
+        vf_reset: false,
+        clipping: true,
+        jump: true,
+        display_wait: false,
+    };
+
+    /// XO-CHIP, as implemented by Octo
+    pub const XO_CHIP: Quirks = Quirks {
+        shift: false,
+        load_store: false,
+        vf_reset: false,
+        clipping: false,
+        jump: false,
+        display_wait: false,
+    };
+
+    /// The behaviour most modern CHIP-8 programs expect
+    pub const MODERN: Quirks = Quirks {
+        shift: false,
+        load_store: false,
+        vf_reset: false,
+        clipping: true,
+        jump: false,
+        display_wait: false,
+    };
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::COSMAC_VIP
+    }
+}
+
+/// Named quirk presets that can be selected at startup
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuirkProfile {
+    #[default]
+    CosmacVip,
+    Chip48,
+    Schip11,
+    XoChip,
+    Modern,
+}
+
+impl QuirkProfile {
+    /// Get the quirks used by this profile
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            QuirkProfile::CosmacVip => Quirks::COSMAC_VIP,
+            QuirkProfile::Chip48 => Quirks::CHIP_48,
+            QuirkProfile::Schip11 => Quirks::SCHIP_1_1,
+            QuirkProfile::XoChip => Quirks::XO_CHIP,
+            QuirkProfile::Modern => Quirks::MODERN,
+        }
+    }
+
+    /// Get the platform this profile runs on
+    pub fn platform(&self) -> Platform {
+        match self {
+            QuirkProfile::CosmacVip | QuirkProfile::Chip48 => Platform::Chip8,
+            QuirkProfile::Schip11 | QuirkProfile::Modern => Platform::SuperChip,
+            QuirkProfile::XoChip => Platform::XoChip,
+        }
+    }
+
+    /// Get the name used to select this profile
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuirkProfile::CosmacVip => "cosmac",
+            QuirkProfile::Chip48 => "chip48",
+            QuirkProfile::Schip11 => "schip",
+            QuirkProfile::XoChip => "xochip",
+            QuirkProfile::Modern => "modern",
+        }
+    }
+}
+
+impl fmt::Display for QuirkProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for QuirkProfile {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace(['-', '_', ' ', '.'], "").as_str() {
+            "cosmac" | "cosmacvip" | "vip" | "chip8" => Ok(QuirkProfile::CosmacVip),
+            "chip48" => Ok(QuirkProfile::Chip48),
+            "schip" | "schip11" | "superchip" | "superchip11" => Ok(QuirkProfile::Schip11),
+            "xochip" | "xo" | "octo" => Ok(QuirkProfile::XoChip),
+            "modern" => Ok(QuirkProfile::Modern),
+            _ => Err("Unknown quirk profile. Expected one of: cosmac, chip48, schip, xochip, modern"),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+
+// Save states are taken once per frame, which runs at 60 Hz
+const FRAMES_PER_SECOND: usize = 60;
+
+/// Ring buffer of save states used to rewind the machine frame by frame.
+/// Only the newest snapshot is kept in full. Older snapshots are stored as the
+///     delta between them and the snapshot after them, which keeps memory low
+///     since only a few bytes of the machine change each frame.
+pub struct RewindBuffer {
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+}
+
+/// How to get from a snapshot to the one taken before it
+enum Delta {
+    /// The snapshots have different lengths, so the older one is stored in full
+    Full(Vec<u8>),
+
+    /// Run-length encoded XOR of the two snapshots
+    Xor(Vec<u8>),
+}
+
+impl RewindBuffer {
+    /// Create a rewind buffer that holds up to `capacity` snapshots
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer {
+            capacity: capacity.max(1),
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Create a rewind buffer that holds the snapshots of the last `seconds` seconds of frames
+    pub fn with_seconds(seconds: usize) -> Self {
+        RewindBuffer::new(seconds * FRAMES_PER_SECOND)
+    }
+
+    /// Number of snapshots in the buffer
+    pub fn len(&self) -> usize {
+        match self.latest {
+            Some(_) => self.deltas.len() + 1,
+            None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Remove all snapshots
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    /// Add a new snapshot, dropping the oldest one if the buffer is full
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            self.deltas.push_back(Delta::encode(&snapshot, previous));
+        }
+        self.latest = Some(snapshot);
+
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    /// Remove and return the newest snapshot
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let latest: Vec<u8> = self.latest.take()?;
+        self.latest = self.deltas.pop_back().map(|delta| delta.apply(&latest));
+        Some(latest)
+    }
+}
+
+impl Delta {
+    /// Encode the delta that turns `newer` back into `older`
+    fn encode(newer: &[u8], older: Vec<u8>) -> Self {
+        if newer.len() != older.len() {
+            return Delta::Full(older);
+        }
+
+        // Runs are stored as [unchanged byte count: u16][changed byte count: u16][changed bytes XOR newer]
+        let mut encoded: Vec<u8> = Vec::new();
+        let mut position: usize = 0;
+        while position < newer.len() {
+            let unchanged_start: usize = position;
+            while position < newer.len()
+                && newer[position] == older[position]
+                && position - unchanged_start < u16::MAX as usize {
+                position += 1;
+            }
+            let unchanged: usize = position - unchanged_start;
+
+            let changed_start: usize = position;
+            while position < newer.len()
+                && newer[position] != older[position]
+                && position - changed_start < u16::MAX as usize {
+                position += 1;
+            }
+
+            encoded.extend_from_slice(&(unchanged as u16).to_le_bytes());
+            encoded.extend_from_slice(&((position - changed_start) as u16).to_le_bytes());
+            for index in changed_start..position {
+                encoded.push(newer[index] ^ older[index]);
+            }
+        }
+
+        Delta::Xor(encoded)
+    }
+
+    /// Turn `newer` back into the snapshot taken before it
+    fn apply(self, newer: &[u8]) -> Vec<u8> {
+        let encoded: Vec<u8> = match self {
+            Delta::Full(older) => return older,
+            Delta::Xor(encoded) => encoded,
+        };
+
+        let mut older: Vec<u8> = newer.to_vec();
+        let mut position: usize = 0;
+        let mut cursor: usize = 0;
+        while cursor < encoded.len() {
+            let unchanged: usize = u16::from_le_bytes([encoded[cursor], encoded[cursor + 1]]) as usize;
+            let changed: usize = u16::from_le_bytes([encoded[cursor + 2], encoded[cursor + 3]]) as usize;
+            cursor += 4;
+            position += unchanged;
+
+            for byte in &encoded[cursor..cursor + changed] {
+                older[position] ^= byte;
+                position += 1;
+            }
+            cursor += changed;
+        }
+
+        older
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Snapshots that change a few bytes per frame, like a running machine
+    fn snapshots(count: usize, length: usize) -> Vec<Vec<u8>> {
+        let mut state: u32 = 0x12345678;
+        let mut snapshot: Vec<u8> = vec![0; length];
+        (0..count)
+            .map(|_| {
+                for _i in 0..8 {
+                    state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                    snapshot[(state >> 8) as usize % length] = (state >> 24) as u8;
+                }
+                snapshot.clone()
+            })
+            .collect()
+    }
+
+    fn push_all(buffer: &mut RewindBuffer, snapshots: &[Vec<u8>]) {
+        for snapshot in snapshots {
+            buffer.push(snapshot.clone());
+        }
+    }
+
+    #[test]
+    fn snapshots_come_back_identical_newest_first() {
+        let snapshots: Vec<Vec<u8>> = snapshots(500, 4096);
+        let mut buffer: RewindBuffer = RewindBuffer::new(1000);
+        push_all(&mut buffer, &snapshots);
+        assert_eq!(buffer.len(), 500);
+
+        for snapshot in snapshots.iter().rev() {
+            assert_eq!(buffer.pop().as_ref(), Some(snapshot));
+        }
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn full_buffer_drops_the_oldest_snapshots() {
+        let snapshots: Vec<Vec<u8>> = snapshots(10, 64);
+        let mut buffer: RewindBuffer = RewindBuffer::new(4);
+        push_all(&mut buffer, &snapshots);
+        assert_eq!(buffer.len(), 4);
+
+        for snapshot in snapshots[6..].iter().rev() {
+            assert_eq!(buffer.pop().as_ref(), Some(snapshot));
+        }
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn pushing_after_popping_continues_from_the_popped_snapshot() {
+        let snapshots: Vec<Vec<u8>> = snapshots(6, 64);
+        let mut buffer: RewindBuffer = RewindBuffer::new(10);
+        push_all(&mut buffer, &snapshots[..4]);
+        buffer.pop();
+        buffer.pop();
+        push_all(&mut buffer, &snapshots[4..]);
+
+        assert_eq!(buffer.pop().as_ref(), Some(&snapshots[5]));
+        assert_eq!(buffer.pop().as_ref(), Some(&snapshots[4]));
+        assert_eq!(buffer.pop().as_ref(), Some(&snapshots[1]));
+        assert_eq!(buffer.pop().as_ref(), Some(&snapshots[0]));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn snapshots_of_different_lengths_are_kept_in_full() {
+        // e.g. a save state of another platform with more memory
+        let small: Vec<u8> = vec![1; 4096];
+        let large: Vec<u8> = vec![2; 65536];
+        let mut buffer: RewindBuffer = RewindBuffer::new(10);
+        push_all(&mut buffer, &[small.clone(), large.clone(), small.clone()]);
+
+        assert_eq!(buffer.pop(), Some(small.clone()));
+        assert_eq!(buffer.pop(), Some(large));
+        assert_eq!(buffer.pop(), Some(small));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn runs_longer_than_a_run_length_are_split() {
+        // Runs are counted in 16 bits, so these need several runs each
+        let length: usize = 3 * u16::MAX as usize;
+        let older: Vec<u8> = vec![0; length];
+        let mut newer: Vec<u8> = older.clone();
+        newer[..u16::MAX as usize + 10].fill(0xFF);
+        newer[length - 1] = 1;
+
+        let mut buffer: RewindBuffer = RewindBuffer::new(2);
+        push_all(&mut buffer, &[older.clone(), newer.clone()]);
+        assert_eq!(buffer.pop(), Some(newer));
+        assert_eq!(buffer.pop(), Some(older));
+    }
+
+    #[test]
+    fn identical_snapshots_round_trip() {
+        let snapshot: Vec<u8> = vec![7; 100];
+        let mut buffer: RewindBuffer = RewindBuffer::with_seconds(1);
+        push_all(&mut buffer, &[snapshot.clone(), snapshot.clone(), Vec::new(), Vec::new()]);
+        assert_eq!(buffer.pop(), Some(Vec::new()));
+        assert_eq!(buffer.pop(), Some(Vec::new()));
+        assert_eq!(buffer.pop(), Some(snapshot.clone()));
+        assert_eq!(buffer.pop(), Some(snapshot));
+    }
+}
@@ -0,0 +1,87 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+/// Seedable random number generator used by the Cxkk instruction.
+/// The position in its stream can be read and set directly, so restoring it to the exact same
+///     point later on takes the same time however many bytes it has produced.
+pub struct Chip8Rng {
+    seed: u64,
+    draws: u64,
+    rng: ChaCha12Rng,
+}
+
+impl Chip8Rng {
+    /// Create a generator with the given seed. The seed fully determines the bytes it produces,
+    ///     and it is written to save states so a restored machine draws the same numbers.
+    pub fn new(seed: u64) -> Self {
+        Chip8Rng {
+            seed,
+            draws: 0,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Create a generator with a random seed
+    pub fn from_entropy() -> Self {
+        Chip8Rng::new(rand::random())
+    }
+
+    /// Create a generator with the given seed at a position in its stream given by `word_position`,
+    ///     which has already produced `draws` bytes
+    pub fn restore(seed: u64, draws: u64, word_position: u128) -> Self {
+        let mut rng: ChaCha12Rng = ChaCha12Rng::seed_from_u64(seed);
+        rng.set_word_pos(word_position);
+        Chip8Rng { seed, draws, rng }
+    }
+
+    /// Seed the generator was created with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of bytes produced since the generator was seeded
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+
+    /// Position of the generator in its stream of 32-bit words
+    pub fn word_position(&self) -> u128 {
+        self.rng.get_word_pos()
+    }
+
+    /// Get a random byte in the range 0x00 to 0xFF
+    pub fn next_byte(&mut self) -> u8 {
+        self.draws += 1;
+        self.rng.gen()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restored_rng_continues_the_same_stream() {
+        let mut rng: Chip8Rng = Chip8Rng::new(7);
+        for _i in 0..1000 {
+            rng.next_byte();
+        }
+        let mut restored: Chip8Rng = Chip8Rng::restore(rng.seed(), rng.draws(), rng.word_position());
+        let expected: Vec<u8> = (0..100).map(|_| rng.next_byte()).collect();
+        let actual: Vec<u8> = (0..100).map(|_| restored.next_byte()).collect();
+        assert_eq!(actual, expected);
+        assert_eq!(restored.draws(), 1100);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_bytes() {
+        let mut first: Chip8Rng = Chip8Rng::new(0xC8);
+        let mut second: Chip8Rng = Chip8Rng::new(0xC8);
+        let mut other: Chip8Rng = Chip8Rng::new(0xC9);
+        let bytes: Vec<u8> = (0..64).map(|_| first.next_byte()).collect();
+        assert_eq!(bytes, (0..64).map(|_| second.next_byte()).collect::<Vec<u8>>());
+        assert_ne!(bytes, (0..64).map(|_| other.next_byte()).collect::<Vec<u8>>());
+        assert_eq!(first.seed(), 0xC8);
+        assert_eq!(first.draws(), 64);
+    }
+}
@@ -0,0 +1,223 @@
+use super::{platform::Platform, quirks::Quirks};
+use crate::error::Chip8Error;
+
+/// Identifies a save state file
+pub const STATE_MAGIC: [u8; 4] = *b"RC8S";
+
+/// Version of the save state format. Increase this whenever the layout changes.
+pub const STATE_VERSION: u8 = 2;
+
+/// Writes the little-endian binary save state format
+pub struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut buffer: Vec<u8> = Vec::new();
+        buffer.extend_from_slice(&STATE_MAGIC);
+        buffer.push(STATE_VERSION);
+        StateWriter { buffer }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buffer.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u128(&mut self, value: u128) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        StateWriter::new()
+    }
+}
+
+/// Reads the little-endian binary save state format
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Start reading a save state, checking its header
+    pub fn new(data: &'a [u8]) -> Result<Self, Chip8Error> {
+        let mut reader: StateReader = StateReader { data, position: 0 };
+        if reader.read_bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(Chip8Error::InvalidState("Not a save state file"));
+        }
+        if reader.read_u8()? != STATE_VERSION {
+            return Err(Chip8Error::InvalidState("Unsupported save state version"));
+        }
+        Ok(reader)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, Chip8Error> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Chip8Error::InvalidState("Save state contains an invalid flag")),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Chip8Error> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Chip8Error> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, Chip8Error> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u128(&mut self) -> Result<u128, Chip8Error> {
+        Ok(u128::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Chip8Error> {
+        let mut array: [u8; N] = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], Chip8Error> {
+        if self.position + length > self.data.len() {
+            return Err(Chip8Error::InvalidState("Save state is truncated"));
+        }
+        let bytes: &'a [u8] = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    /// Check that the whole save state has been read
+    pub fn finish(self) -> Result<(), Chip8Error> {
+        if self.position != self.data.len() {
+            return Err(Chip8Error::InvalidState("Save state has unexpected trailing data"));
+        }
+        Ok(())
+    }
+}
+
+/// Encode a platform as a single byte
+pub fn platform_to_u8(platform: Platform) -> u8 {
+    match platform {
+        Platform::Chip8 => 0,
+        Platform::SuperChip => 1,
+        Platform::XoChip => 2,
+    }
+}
+
+/// Decode a platform from a single byte
+pub fn platform_from_u8(value: u8) -> Result<Platform, Chip8Error> {
+    match value {
+        0 => Ok(Platform::Chip8),
+        1 => Ok(Platform::SuperChip),
+        2 => Ok(Platform::XoChip),
+        _ => Err(Chip8Error::InvalidState("Save state contains an unknown platform")),
+    }
+}
+
+/// Encode the quirks as a bitfield
+pub fn quirks_to_u8(quirks: Quirks) -> u8 {
+    (quirks.shift as u8)
+        | (quirks.load_store as u8) << 1
+        | (quirks.vf_reset as u8) << 2
+        | (quirks.clipping as u8) << 3
+        | (quirks.jump as u8) << 4
+        | (quirks.display_wait as u8) << 5
+}
+
+/// Decode the quirks from a bitfield
+pub fn quirks_from_u8(value: u8) -> Quirks {
+    Quirks {
+        shift: value & 1 != 0,
+        load_store: value & (1 << 1) != 0,
+        vf_reset: value & (1 << 2) != 0,
+        clipping: value & (1 << 3) != 0,
+        jump: value & (1 << 4) != 0,
+        display_wait: value & (1 << 5) != 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::quirks::QuirkProfile;
+
+    #[test]
+    fn values_round_trip() {
+        let mut writer: StateWriter = StateWriter::new();
+        writer.write_u8(0xAB);
+        writer.write_bool(true);
+        writer.write_u16(0x1234);
+        writer.write_u32(0xDEADBEEF);
+        writer.write_u64(u64::MAX - 1);
+        writer.write_u128(1 << 100);
+        writer.write_bytes(&[1, 2, 3]);
+        let data: Vec<u8> = writer.finish();
+
+        let mut reader: StateReader = StateReader::new(&data).unwrap();
+        assert_eq!(reader.read_u8().unwrap(), 0xAB);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_u16().unwrap(), 0x1234);
+        assert_eq!(reader.read_u32().unwrap(), 0xDEADBEEF);
+        assert_eq!(reader.read_u64().unwrap(), u64::MAX - 1);
+        assert_eq!(reader.read_u128().unwrap(), 1 << 100);
+        assert_eq!(reader.read_array::<3>().unwrap(), [1, 2, 3]);
+        assert!(reader.read_u8().is_err());
+        reader.finish().unwrap();
+    }
+
+    #[test]
+    fn platforms_and_quirks_round_trip() {
+        for profile in [QuirkProfile::CosmacVip, QuirkProfile::Chip48, QuirkProfile::Schip11, QuirkProfile::XoChip, QuirkProfile::Modern] {
+            let platform: Platform = profile.platform();
+            assert_eq!(platform_from_u8(platform_to_u8(platform)).unwrap(), platform);
+            assert_eq!(quirks_from_u8(quirks_to_u8(profile.quirks())), profile.quirks());
+        }
+        assert!(platform_from_u8(3).is_err());
+    }
+
+    #[test]
+    fn invalid_headers_and_flags() {
+        assert!(StateReader::new(b"RC8").is_err());
+        assert!(StateReader::new(b"XXXX\x02").is_err());
+        assert!(StateReader::new(&[b'R', b'C', b'8', b'S', STATE_VERSION + 1]).is_err());
+
+        let data: Vec<u8> = [&STATE_MAGIC[..], &[STATE_VERSION, 2]].concat();
+        let mut reader: StateReader = StateReader::new(&data).unwrap();
+        assert!(reader.read_bool().is_err());
+    }
+}
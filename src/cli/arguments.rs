@@ -0,0 +1,360 @@
+use std::path::PathBuf;
+use rusty_chip8::chip8::opcode_policy::OpcodePolicy;
+use rusty_chip8::chip8::quirks::QuirkProfile;
+use rusty_chip8::debug::trace::TraceFilter;
+use crate::drivers::config_driver::{parse_colour, Settings};
+use crate::gui::filters::Filter;
+use crate::gui::palette::Theme;
+use crate::gui::persistence::Persistence;
+
+// How long a headless run lasts if the ROM doesn't exit by itself
+const DEFAULT_HEADLESS_FRAMES: usize = 600;
+
+pub const USAGE: &str = "\
+Usage:
+  RustyCHIP8 [options] [rom]             Run a ROM, opening a file dialog if no ROM is given
+  RustyCHIP8 disasm <rom> [--quirks <p>] Print the disassembly of a ROM
+  RustyCHIP8 asm <source> [-o <rom>]     Assemble a source file into a ROM
+
+Options:
+  --quirks <profile>       cosmac, chip48, schip, xochip or modern (default cosmac)
+  --ipf <n>                Instructions executed per 60 Hz frame
+  --scale <n>              Size of a low resolution pixel when the window opens (default 10)
+  --theme <name>           classic, green, amber, lcd, octo or custom (default classic)
+  --palette <colours>      Comma separated hex colours for the custom theme: background, plane 1, plane 2, both planes
+  --persistence <mode>     Keep erased pixels visible to reduce flicker: off, decay or merge (default off)
+  --filters <list>         Comma separated display filters: grid, scanlines, bloom, curvature
+  --mute                   Don't play any sound
+  --seed <n>               Seed the random number generator
+  --rewind-seconds <n>     How far back rewinding reaches (default 10)
+  --unknown-opcodes <p>    ignore, halt or break into the debugger (default ignore)
+  --debug                  Control the machine from a debugger REPL on the terminal
+  --trace <file>           Write every executed instruction to a file
+  --trace-pc <start-end>   Only trace instructions in a range of hex addresses, e.g. 200-2FF
+  --trace-class <digits>   Only trace instructions starting with these hex digits, e.g. 8,D
+  --profile <file>         Write a profile of the run when the emulator exits, as JSON if the file ends in .json
+  --headless               Run without a window and print the display when done
+  --frames <n>             Number of frames a headless run lasts (default 600)
+  -h, --help               Show this help";
+
+/// What the emulator was asked to do
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Disassemble,
+    Assemble { output: Option<PathBuf> },
+    Help,
+}
+
+impl Command {
+    /// The subcommand as it is typed, if it isn't the default of running a ROM
+    fn name(&self) -> Option<&'static str> {
+        match self {
+            Command::Disassemble => Some("disasm"),
+            Command::Assemble { .. } => Some("asm"),
+            Command::Run | Command::Help => None,
+        }
+    }
+
+    /// Whether an option does anything for this command
+    fn accepts(&self, option: &str) -> bool {
+        match self {
+            Command::Disassemble => matches!(option, "--quirks" | "-h" | "--help"),
+            Command::Assemble { .. } => matches!(option, "-o" | "--output" | "-h" | "--help"),
+            Command::Run | Command::Help => true,
+        }
+    }
+}
+
+/// Parsed command line arguments
+#[derive(Clone, Debug)]
+pub struct Arguments {
+    pub command: Command,
+    // The ROM to run or disassemble, or the source to assemble
+    pub path: Option<PathBuf>,
+    pub quirks: Option<QuirkProfile>,
+    pub instructions_per_frame: Option<u16>,
+    pub scale: Option<u32>,
+    pub theme: Option<Theme>,
+    pub palette: Option<Vec<String>>,
+    pub persistence: Option<Persistence>,
+    pub filters: Option<Vec<Filter>>,
+    pub mute: bool,
+    pub seed: Option<u64>,
+    pub rewind_seconds: Option<usize>,
+    pub opcode_policy: Option<OpcodePolicy>,
+    pub debug: bool,
+    pub trace: Option<PathBuf>,
+    pub trace_filter: TraceFilter,
+    pub profile: Option<PathBuf>,
+    pub headless: bool,
+    pub frames: usize,
+}
+
+impl Default for Arguments {
+    fn default() -> Self {
+        Arguments {
+            command: Command::Run,
+            path: None,
+            quirks: None,
+            instructions_per_frame: None,
+            scale: None,
+            theme: None,
+            palette: None,
+            persistence: None,
+            filters: None,
+            mute: false,
+            seed: None,
+            rewind_seconds: None,
+            opcode_policy: None,
+            debug: false,
+            trace: None,
+            trace_filter: TraceFilter::default(),
+            profile: None,
+            headless: false,
+            frames: DEFAULT_HEADLESS_FRAMES,
+        }
+    }
+}
+
+impl Arguments {
+    /// Parse the arguments the emulator was started with
+    pub fn from_env() -> Result<Self, String> {
+        Arguments::parse(std::env::args().skip(1))
+    }
+
+    /// Parse command line arguments, not including the program name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut arguments: Arguments = Arguments::default();
+        let mut args = args.into_iter().peekable();
+
+        match args.peek().map(String::as_str) {
+            Some("disasm") => arguments.command = Command::Disassemble,
+            Some("asm") => arguments.command = Command::Assemble { output: None },
+            _ => {}
+        }
+        if arguments.command != Command::Run {
+            args.next();
+        }
+
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| -> Result<String, String> {
+                args.next().ok_or_else(|| format!("{} needs a value", flag))
+            };
+
+            match arg.as_str() {
+                "--quirks" => arguments.quirks = Some(value(&arg)?.parse().map_err(String::from)?),
+                "--ipf" => {
+                    let ipf: u16 = parse_number(&arg, &value(&arg)?)?;
+                    if ipf == 0 {
+                        return Err(String::from("--ipf must be at least 1"));
+                    }
+                    arguments.instructions_per_frame = Some(ipf);
+                }
+                "--scale" => {
+                    let scale: u32 = parse_number(&arg, &value(&arg)?)?;
+                    if scale == 0 {
+                        return Err(String::from("--scale must be at least 1"));
+                    }
+                    arguments.scale = Some(scale);
+                }
+                "--theme" => arguments.theme = Some(value(&arg)?.parse().map_err(String::from)?),
+                "--palette" => {
+                    let colours: Vec<String> = value(&arg)?.split(',').map(String::from).collect();
+                    for colour in &colours {
+                        parse_colour(colour)?;
+                    }
+                    arguments.palette = Some(colours);
+                }
+                "--persistence" => arguments.persistence = Some(value(&arg)?.parse().map_err(String::from)?),
+                "--filters" => arguments.filters = Some(Filter::parse_list(&value(&arg)?)?),
+                "--mute" => arguments.mute = true,
+                "--seed" => arguments.seed = Some(parse_number(&arg, &value(&arg)?)?),
+                "--rewind-seconds" => arguments.rewind_seconds = Some(parse_number(&arg, &value(&arg)?)?),
+                "--unknown-opcodes" => arguments.opcode_policy = Some(value(&arg)?.parse().map_err(String::from)?),
+                "--debug" => arguments.debug = true,
+                "--trace" => arguments.trace = Some(PathBuf::from(value(&arg)?)),
+                "--trace-pc" => arguments.trace_filter.pc_range = Some(TraceFilter::parse_range(&value(&arg)?)?),
+                "--trace-class" => arguments.trace_filter.classes = TraceFilter::parse_classes(&value(&arg)?)?,
+                "--profile" => arguments.profile = Some(PathBuf::from(value(&arg)?)),
+                "--headless" => arguments.headless = true,
+                "--frames" => arguments.frames = parse_number(&arg, &value(&arg)?)?,
+                "-o" | "--output" => match &mut arguments.command {
+                    Command::Assemble { output } => *output = Some(PathBuf::from(value(&arg)?)),
+                    _ => return Err(format!("{} is only used by the asm command", arg)),
+                },
+                "-h" | "--help" => arguments.command = Command::Help,
+                _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+                _ => {
+                    if arguments.path.is_some() {
+                        return Err(format!("Unexpected argument: {}", arg));
+                    }
+                    arguments.path = Some(PathBuf::from(arg));
+                    continue;
+                }
+            }
+
+            if let Some(name) = arguments.command.name() {
+                if !arguments.command.accepts(&arg) {
+                    return Err(format!("{} can't be used with the {} command", arg, name));
+                }
+            }
+        }
+
+        Ok(arguments)
+    }
+
+    /// Settings given on the command line, which take precedence over the configuration file
+    pub fn settings(&self) -> Settings {
+        Settings {
+            scale: self.scale,
+            filters: self.filters
+                .as_ref()
+                .map(|filters| filters.iter().map(|filter| filter.name().to_string()).collect()),
+            mute: if self.mute { Some(true) } else { None },
+            rewind_seconds: self.rewind_seconds,
+            unknown_opcodes: self.opcode_policy.map(|policy| policy.name().to_string()),
+            ..self.remembered_settings()
+        }
+    }
+
+    /// Settings given on the command line that are remembered for the ROM being run
+    pub fn remembered_settings(&self) -> Settings {
+        Settings {
+            quirks: self.quirks.map(|profile| profile.name().to_string()),
+            ipf: self.instructions_per_frame,
+            // Colours given without a theme are meant to be seen
+            theme: self.theme
+                .or(self.palette.as_ref().map(|_| Theme::Custom))
+                .map(|theme| theme.name().to_string()),
+            palette: self.palette.clone(),
+            persistence: self.persistence.map(|persistence| persistence.name().to_string()),
+            ..Settings::default()
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|e| format!("Invalid value for {}: {} ({})", flag, value, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Arguments, String> {
+        Arguments::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn rom_path_is_positional() {
+        let arguments: Arguments = parse(&["--mute", "pong.ch8", "--ipf", "20"]).unwrap();
+        assert_eq!(arguments.command, Command::Run);
+        assert_eq!(arguments.path, Some(PathBuf::from("pong.ch8")));
+        assert_eq!(parse(&["pong.ch8", "tetris.ch8"]).unwrap_err(), "Unexpected argument: tetris.ch8");
+    }
+
+    #[test]
+    fn no_rom_opens_the_file_dialog() {
+        // The window asks for a ROM when no path is given
+        let arguments: Arguments = parse(&[]).unwrap();
+        assert_eq!(arguments.command, Command::Run);
+        assert_eq!(arguments.path, None);
+        assert_eq!(arguments.frames, DEFAULT_HEADLESS_FRAMES);
+        assert_eq!(arguments.settings(), Settings::default());
+    }
+
+    #[test]
+    fn every_option_is_read() {
+        let arguments: Arguments = parse(&[
+            "--quirks", "schip",
+            "--ipf", "30",
+            "--scale", "4",
+            "--theme", "amber",
+            "--palette", "000000,FF8800",
+            "--persistence", "decay",
+            "--filters", "bloom,scanlines",
+            "--mute",
+            "--seed", "42",
+            "--rewind-seconds", "5",
+            "--unknown-opcodes", "break",
+            "--debug",
+            "--trace", "trace.txt",
+            "--trace-pc", "200-2FF",
+            "--trace-class", "8,D",
+            "--profile", "profile.json",
+            "--headless",
+            "--frames", "120",
+        ]).unwrap();
+
+        assert_eq!(arguments.quirks, Some(QuirkProfile::Schip11));
+        assert_eq!(arguments.instructions_per_frame, Some(30));
+        assert_eq!(arguments.scale, Some(4));
+        assert_eq!(arguments.theme, Some(Theme::Amber));
+        assert_eq!(arguments.palette, Some(vec![String::from("000000"), String::from("FF8800")]));
+        assert_eq!(arguments.persistence, Some(Persistence::Decay));
+        assert_eq!(arguments.filters, Some(vec![Filter::Scanlines, Filter::Bloom]));
+        assert!(arguments.mute);
+        assert_eq!(arguments.seed, Some(42));
+        assert_eq!(arguments.rewind_seconds, Some(5));
+        assert_eq!(arguments.opcode_policy, Some(OpcodePolicy::Break));
+        assert!(arguments.debug);
+        assert_eq!(arguments.trace, Some(PathBuf::from("trace.txt")));
+        assert_eq!(arguments.trace_filter.pc_range, Some(0x200..=0x2FF));
+        assert_eq!(arguments.trace_filter.classes, vec![0x8, 0xD]);
+        assert_eq!(arguments.profile, Some(PathBuf::from("profile.json")));
+        assert!(arguments.headless);
+        assert_eq!(arguments.frames, 120);
+        assert_eq!(arguments.path, None);
+    }
+
+    #[test]
+    fn missing_and_invalid_values() {
+        assert_eq!(parse(&["--ipf"]).unwrap_err(), "--ipf needs a value");
+        assert_eq!(parse(&["pong.ch8", "--trace"]).unwrap_err(), "--trace needs a value");
+        assert_eq!(
+            parse(&["--ipf", "fast"]).unwrap_err(),
+            "Invalid value for --ipf: fast (invalid digit found in string)"
+        );
+        assert!(parse(&["--seed", "-1"]).unwrap_err().starts_with("Invalid value for --seed: -1"));
+        assert!(parse(&["--frames", "99999999999999999999999"]).is_err());
+        assert_eq!(parse(&["--ipf", "0"]).unwrap_err(), "--ipf must be at least 1");
+        assert_eq!(parse(&["--scale", "0"]).unwrap_err(), "--scale must be at least 1");
+        assert!(parse(&["--quirks", "chip9"]).is_err());
+        assert!(parse(&["--theme", "purple"]).is_err());
+        assert_eq!(parse(&["--palette", "000000,FFF"]).unwrap_err(), "Invalid colour: FFF");
+        assert!(parse(&["--filters", "blur"]).is_err());
+        assert!(parse(&["--trace-pc", "2FF-200"]).is_err());
+        assert_eq!(parse(&["--fast"]).unwrap_err(), "Unknown option: --fast");
+    }
+
+    #[test]
+    fn subcommands() {
+        let arguments: Arguments = parse(&["disasm", "pong.ch8", "--quirks", "xochip"]).unwrap();
+        assert_eq!(arguments.command, Command::Disassemble);
+        assert_eq!(arguments.path, Some(PathBuf::from("pong.ch8")));
+        assert_eq!(arguments.quirks, Some(QuirkProfile::XoChip));
+
+        let arguments: Arguments = parse(&["asm", "pong.8o", "-o", "pong.ch8"]).unwrap();
+        assert_eq!(arguments.command, Command::Assemble { output: Some(PathBuf::from("pong.ch8")) });
+        assert_eq!(arguments.path, Some(PathBuf::from("pong.8o")));
+
+        assert_eq!(parse(&["pong.ch8", "-o", "out.ch8"]).unwrap_err(), "-o is only used by the asm command");
+        assert_eq!(parse(&["disasm", "--help"]).unwrap().command, Command::Help);
+        assert_eq!(parse(&["-h"]).unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn subcommands_reject_options_for_running() {
+        assert_eq!(
+            parse(&["disasm", "pong.ch8", "--scale", "4"]).unwrap_err(),
+            "--scale can't be used with the disasm command"
+        );
+        assert_eq!(parse(&["disasm", "pong.ch8", "--headless"]).unwrap_err(), "--headless can't be used with the disasm command");
+        assert_eq!(parse(&["asm", "pong.8o", "--mute"]).unwrap_err(), "--mute can't be used with the asm command");
+        assert_eq!(parse(&["asm", "pong.8o", "--quirks", "schip"]).unwrap_err(), "--quirks can't be used with the asm command");
+    }
+}
@@ -1,6 +1,276 @@
-pub struct Debugger { }
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Write;
+
+use crate::asm::instruction::Instruction;
+use crate::chip8::cpu::{FrameEvent, CHIP8};
+use crate::chip8::op_code::OpCode;
+use crate::error::Chip8Error;
+
+/// Interactive debugger that drives a CHIP8 through CHIP8::run_frame_with instead of CHIP8::run_frame.
+/// It can pause and resume the machine, step through it and stop on breakpoints and watchpoints.
+pub struct Debugger {
+    paused: bool,
+    run_mode: RunMode,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: BTreeSet<u16>,
+    // Set when resuming from a breakpoint so it doesn't trigger again straight away
+    resume_from: Option<u16>,
+}
+
+/// What the debugger is waiting for while the machine runs
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    Continue,
+    StepOver { return_address: u16, stack_pointer: u8 },
+    RunToReturn { stack_pointer: u8 },
+}
+
+/// Why the debugger paused the machine
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BreakReason {
+    Breakpoint(u16),
+    Watchpoint { address: u16, pc: u16 },
+    Step(u16),
+    Return(u16),
+    Paused(u16),
+    // The instruction at the program counter faulted, see Chip8Error
+    Fault(String),
+}
+
+impl fmt::Display for BreakReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakReason::Breakpoint(pc) => write!(f, "Breakpoint hit at {:#05X}", pc),
+            BreakReason::Watchpoint { address, pc } => {
+                write!(f, "Watchpoint {:#05X} written by instruction at {:#05X}", address, pc)
+            }
+            BreakReason::Step(pc) => write!(f, "Stepped to {:#05X}", pc),
+            BreakReason::Return(pc) => write!(f, "Returned to {:#05X}", pc),
+            BreakReason::Paused(pc) => write!(f, "Paused at {:#05X}", pc),
+            BreakReason::Fault(message) => write!(f, "Fault: {}", message),
+        }
+    }
+}
 
 impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            paused: false,
+            run_mode: RunMode::Continue,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            resume_from: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause the machine at the current instruction
+    pub fn pause(&mut self, processor: &CHIP8) -> BreakReason {
+        self.paused = true;
+        self.run_mode = RunMode::Continue;
+        BreakReason::Paused(processor.pc())
+    }
+
+    /// Resume running until a breakpoint or watchpoint is hit
+    pub fn resume(&mut self, processor: &CHIP8) {
+        self.start(processor, RunMode::Continue);
+    }
+
+    /// Run until the instruction after the current one.
+    /// A CALL is stepped over entirely, otherwise this is the same as a single step.
+    pub fn step_over(&mut self, processor: &mut CHIP8) -> Option<BreakReason> {
+        let op_code: OpCode = match processor.current_op_code() {
+            Ok(op_code) => OpCode::new(op_code),
+            Err(e) => return Some(self.break_with(BreakReason::Fault(e.to_string()))),
+        };
+        // A CALL in the last word of memory can't return, so it is stepped into
+        let return_address: u16 = match processor.pc().checked_add(2) {
+            Some(return_address) if op_code.split_op_code().0 == 0x2 => return_address,
+            _ => return Some(self.step(processor)),
+        };
+
+        self.start(processor, RunMode::StepOver {
+            return_address,
+            stack_pointer: processor.stack_pointer(),
+        });
+        None
+    }
+
+    /// Run until the current subroutine returns
+    pub fn run_to_return(&mut self, processor: &CHIP8) -> Result<(), &'static str> {
+        if processor.stack_pointer() == 0 {
+            return Err("Not inside a subroutine");
+        }
+
+        self.start(processor, RunMode::RunToReturn { stack_pointer: processor.stack_pointer() });
+        Ok(())
+    }
+
+    /// Execute a single instruction and pause again
+    pub fn step(&mut self, processor: &mut CHIP8) -> BreakReason {
+        let pc: u16 = processor.pc();
+        let result: Result<(), Chip8Error> = processor.step();
+        self.paused = true;
+        self.run_mode = RunMode::Continue;
+        if let Err(e) = result {
+            return BreakReason::Fault(e.to_string());
+        }
+        self.check_watchpoints(processor, pc).unwrap_or(BreakReason::Step(processor.pc()))
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &u16> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_watchpoint(&mut self, address: u16) {
+        self.watchpoints.insert(address);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &u16> {
+        self.watchpoints.iter()
+    }
+
+    /// Run one 60 Hz frame with CHIP8::run_frame_with, stopping early if the debugger breaks.
+    /// Nothing is executed while the machine is paused.
+    pub fn run_frame(&mut self, processor: &mut CHIP8) -> Option<BreakReason> {
+        if self.paused {
+            return None;
+        }
+
+        let result: Result<Option<BreakReason>, Chip8Error> = processor.run_frame_with(|processor, event| {
+            match event {
+                FrameEvent::Before(pc) => self.check_before(pc),
+                FrameEvent::After(pc) => self.check_after(processor, pc),
+            }
+        });
+        match result {
+            Ok(reason) => reason.map(|reason| self.break_with(reason)),
+            Err(e) => Some(self.break_with(BreakReason::Fault(e.to_string()))),
+        }
+    }
+
+    /// Describe the registers, timers and stack of the machine
+    pub fn describe_registers(processor: &CHIP8) -> String {
+        let mut description: String = String::new();
+        for (index, value) in processor.registers().iter().enumerate() {
+            let separator: &str = if index % 8 == 7 { "\n" } else { "  " };
+            write!(description, "V{:X}={:#04X}{}", index, value, separator).unwrap();
+        }
+        writeln!(
+            description,
+            "I={:#06X}  PC={:#06X}  DT={:#04X}  ST={:#04X}",
+            processor.i(),
+            processor.pc(),
+            processor.delay_timer(),
+            processor.sound_timer
+        ).unwrap();
+        writeln!(description, "{}", Debugger::describe_stack(processor)).unwrap();
+        write!(description, "Next: {}", Debugger::describe_instruction(processor, processor.pc())).unwrap();
+        description
+    }
+
+    /// Describe the instruction at an address as a mnemonic
+    pub fn describe_instruction(processor: &CHIP8, address: u16) -> String {
+        let memory: &[u8] = processor.memory();
+        let word = |offset: usize| -> u16 {
+            let at = |index: usize| memory.get(index).copied().unwrap_or(0) as u16;
+            at(offset) << 8 | at(offset + 1)
+        };
+
+        let address: usize = address as usize;
+        let op_code: u16 = word(address);
+        match Instruction::decode(op_code, word(address + 2)) {
+            Some(instruction) if instruction.is_supported_by(processor.platform()) => format!("{:#06X}  {}", op_code, instruction),
+            _ => format!("{:#06X}  (unknown)", op_code),
+        }
+    }
+
+    /// Describe the return addresses on the stack
+    pub fn describe_stack(processor: &CHIP8) -> String {
+        let addresses: Vec<String> = processor.stack()
+            .iter()
+            .map(|address| format!("{:#06X}", address))
+            .collect();
+        format!("SP={} [{}]", processor.stack_pointer(), addresses.join(", "))
+    }
+
+    /// Hex dump `length` bytes of memory starting at `address`, 16 bytes per line
+    pub fn describe_memory(processor: &CHIP8, address: u16, length: u16) -> String {
+        let memory: &[u8] = processor.memory();
+        let start: usize = (address as usize).min(memory.len());
+        let end: usize = (start + length as usize).min(memory.len());
+
+        let mut description: String = String::new();
+        for (line, chunk) in memory[start..end].chunks(16).enumerate() {
+            let bytes: Vec<String> = chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+            writeln!(description, "{:#06X}: {}", start + line * 16, bytes.join(" ")).unwrap();
+        }
+        description
+    }
+
+    fn start(&mut self, processor: &CHIP8, run_mode: RunMode) {
+        self.paused = false;
+        self.run_mode = run_mode;
+        self.resume_from = Some(processor.pc());
+    }
+
+    fn break_with(&mut self, reason: BreakReason) -> BreakReason {
+        self.paused = true;
+        self.run_mode = RunMode::Continue;
+        reason
+    }
+
+    /// Check for a breakpoint on the instruction about to run
+    fn check_before(&mut self, pc: u16) -> Option<BreakReason> {
+        if self.breakpoints.contains(&pc) && self.resume_from != Some(pc) {
+            return Some(BreakReason::Breakpoint(pc));
+        }
+        self.resume_from = None;
+        None
+    }
+
+    /// Check the watchpoints and whether a step over or run to return is done, after the instruction at `pc` ran
+    fn check_after(&self, processor: &CHIP8, pc: u16) -> Option<BreakReason> {
+        if let Some(reason) = self.check_watchpoints(processor, pc) {
+            return Some(reason);
+        }
+
+        match self.run_mode {
+            RunMode::StepOver { return_address, stack_pointer }
+                if processor.pc() == return_address && processor.stack_pointer() == stack_pointer => {
+                Some(BreakReason::Step(return_address))
+            }
+            RunMode::RunToReturn { stack_pointer } if processor.stack_pointer() < stack_pointer => {
+                Some(BreakReason::Return(processor.pc()))
+            }
+            _ => None,
+        }
+    }
+
+    fn check_watchpoints(&self, processor: &CHIP8, pc: u16) -> Option<BreakReason> {
+        let written = processor.last_write()?;
+        self.watchpoints
+            .iter()
+            .find(|address| written.contains(&(**address as usize)))
+            .map(|address| BreakReason::Watchpoint { address: *address, pc })
+    }
+
     #[cfg(debug_assertions)]
     pub fn dprint(message: String, is_error: bool) {
         if is_error {
@@ -10,13 +280,132 @@ impl Debugger {
         }
     }
 
-    #[cfg(debug_assertions)]
-    pub fn dprint_opcode(message: String, op_code: u16) {
-        println!("{}{:#06X}", message, op_code);
-    }
-
     #[cfg(not(debug_assertions))]
     pub fn dprint(_message: String, _is_error: bool) {}
-    #[cfg(not(debug_assertions))]
-    pub fn dprint_opcode(_message: String, _op_code: u16) {}
-}
\ No newline at end of file
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::quirks::QuirkProfile;
+
+    /// Create a machine for a quirk profile running the given instructions from 0x200
+    fn machine(profile: QuirkProfile, program: &[u16]) -> CHIP8 {
+        let mut processor: CHIP8 = CHIP8::new(profile.platform(), profile.quirks());
+        let rom: Vec<u8> = program.iter().flat_map(|word| word.to_be_bytes()).collect();
+        processor.load_rom_data(&rom).unwrap();
+        processor
+    }
+
+    #[test]
+    fn breakpoint_stops_before_the_instruction() {
+        // LD V0, 1; LD V0, 2; LD V0, 3; JP 0x206
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x6001, 0x6002, 0x6003, 0x1206]);
+        let mut debugger: Debugger = Debugger::new();
+        debugger.add_breakpoint(0x204);
+
+        assert_eq!(debugger.run_frame(&mut processor), Some(BreakReason::Breakpoint(0x204)));
+        assert!(debugger.is_paused());
+        assert_eq!(processor.pc(), 0x204);
+        assert_eq!(processor.registers()[0], 2);
+
+        // Nothing runs while paused, and resuming doesn't stop on the same breakpoint again
+        assert_eq!(debugger.run_frame(&mut processor), None);
+        assert_eq!(processor.pc(), 0x204);
+        debugger.resume(&processor);
+        assert_eq!(debugger.run_frame(&mut processor), None);
+        assert_eq!(processor.registers()[0], 3);
+        assert_eq!(processor.pc(), 0x206);
+    }
+
+    #[test]
+    fn watchpoint_stops_after_bcd_write() {
+        // LD I, 0x300; LD VA, 123; LD B, VA; JP 0x206
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0xA300, 0x6A7B, 0xFA33, 0x1206]);
+        let mut debugger: Debugger = Debugger::new();
+        debugger.add_watchpoint(0x302);
+
+        let reason: Option<BreakReason> = debugger.run_frame(&mut processor);
+        assert_eq!(reason, Some(BreakReason::Watchpoint { address: 0x302, pc: 0x204 }));
+        assert_eq!(processor.pc(), 0x206);
+        assert_eq!(&processor.memory()[0x300..0x303], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn watchpoint_stops_after_register_store() {
+        // LD I, 0x310; LD V0, 5; LD V1, 7; LD [I], V1; JP 0x208
+        let program: [u16; 5] = [0xA310, 0x6005, 0x6107, 0xF155, 0x1208];
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &program);
+        let mut debugger: Debugger = Debugger::new();
+        debugger.add_watchpoint(0x311);
+        let reason: Option<BreakReason> = debugger.run_frame(&mut processor);
+        assert_eq!(reason, Some(BreakReason::Watchpoint { address: 0x311, pc: 0x206 }));
+
+        // A store that doesn't reach the watched address runs on
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &program);
+        let mut debugger: Debugger = Debugger::new();
+        debugger.add_watchpoint(0x312);
+        assert_eq!(debugger.run_frame(&mut processor), None);
+        assert!(debugger.remove_watchpoint(0x312));
+        assert!(!debugger.remove_watchpoint(0x312));
+    }
+
+    #[test]
+    fn step_over_runs_the_whole_call() {
+        // CALL 0x206; LD V0, 1; JP 0x204; LD V1, 5; RET
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x2206, 0x6001, 0x1204, 0x6105, 0x00EE]);
+        let mut debugger: Debugger = Debugger::new();
+
+        assert_eq!(debugger.step_over(&mut processor), None);
+        assert_eq!(debugger.run_frame(&mut processor), Some(BreakReason::Step(0x202)));
+        assert_eq!(processor.registers()[..2], [0, 5]);
+        assert_eq!(processor.stack_pointer(), 0);
+
+        // Any other instruction is a single step
+        assert_eq!(debugger.step_over(&mut processor), Some(BreakReason::Step(0x204)));
+        assert_eq!(processor.registers()[0], 1);
+    }
+
+    #[test]
+    fn step_over_a_call_in_the_last_word_of_memory() {
+        // JP 0xFFE, then LD V0, 0 up to 0xFFFC and CALL 0x200 at 0xFFFE
+        let mut program: Vec<u16> = vec![0x6000; (0x10000 - 0x200) / 2];
+        program[0] = 0x1FFE;
+        *program.last_mut().unwrap() = 0x2200;
+        let mut processor: CHIP8 = machine(QuirkProfile::XoChip, &program);
+        processor.run_cycles(1 + (0xFFFE - 0xFFE) / 2).unwrap();
+        assert_eq!(processor.pc(), 0xFFFE);
+
+        let mut debugger: Debugger = Debugger::new();
+        assert_eq!(debugger.step_over(&mut processor), Some(BreakReason::Step(0x200)));
+    }
+
+    #[test]
+    fn run_to_return_stops_after_ret() {
+        // CALL 0x204; JP 0x202; LD V0, 1; LD V0, 2; RET
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x2204, 0x1202, 0x6001, 0x6002, 0x00EE]);
+        let mut debugger: Debugger = Debugger::new();
+        assert!(debugger.run_to_return(&processor).is_err());
+
+        debugger.step(&mut processor);
+        assert!(debugger.run_to_return(&processor).is_ok());
+        assert_eq!(debugger.run_frame(&mut processor), Some(BreakReason::Return(0x202)));
+        assert_eq!(processor.registers()[0], 2);
+        assert_eq!(processor.stack_pointer(), 0);
+    }
+
+    #[test]
+    fn fault_pauses_the_machine() {
+        let mut processor: CHIP8 = machine(QuirkProfile::CosmacVip, &[0x00EE]);
+        let mut debugger: Debugger = Debugger::new();
+        assert!(matches!(debugger.run_frame(&mut processor), Some(BreakReason::Fault(_))));
+        assert!(debugger.is_paused());
+        assert_eq!(processor.pc(), 0x200);
+    }
+}
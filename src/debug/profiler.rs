@@ -0,0 +1,204 @@
+use std::fmt::Write;
+
+use crate::chip8::cpu::CHIP8;
+use super::debugger::Debugger;
+
+// Timers and the display run at 60 Hz
+const FRAMES_PER_SECOND: f64 = 60.0;
+
+// Number of addresses listed in the text report
+const HOT_SPOT_COUNT: usize = 20;
+
+/// Execution counters collected while a machine runs, to find where a program spends its cycles
+#[derive(Clone, Debug)]
+pub struct Profile {
+    // Executions of the instruction at every address
+    executions: Vec<u64>,
+    // Executions of every instruction class, given by the first hex digit of the opcode
+    classes: [u64; 16],
+    instructions: u64,
+    frames: u64,
+    // Instructions and frames spent in Fx0A waiting for a key
+    key_wait_instructions: u64,
+    key_wait_frames: u64,
+    is_waiting_this_frame: bool,
+    draws: u64,
+    draws_this_frame: u64,
+    max_draws_per_frame: u64,
+}
+
+impl Profile {
+    /// Create a profile for a machine with `memory_size` bytes of memory
+    pub fn new(memory_size: usize) -> Self {
+        Profile {
+            executions: vec![0; memory_size],
+            classes: [0; 16],
+            instructions: 0,
+            frames: 0,
+            key_wait_instructions: 0,
+            key_wait_frames: 0,
+            is_waiting_this_frame: false,
+            draws: 0,
+            draws_this_frame: 0,
+            max_draws_per_frame: 0,
+        }
+    }
+
+    /// Count an executed instruction
+    pub fn record_instruction(&mut self, pc: u16, op_code: u16) {
+        if let Some(count) = self.executions.get_mut(pc as usize) {
+            *count += 1;
+        }
+        self.classes[(op_code >> 12) as usize] += 1;
+        self.instructions += 1;
+    }
+
+    /// Count an Fx0A that found no key pressed
+    pub fn record_key_wait(&mut self) {
+        self.key_wait_instructions += 1;
+        self.is_waiting_this_frame = true;
+    }
+
+    /// Count a sprite drawn by Dxyn
+    pub fn record_draw(&mut self) {
+        self.draws += 1;
+        self.draws_this_frame += 1;
+    }
+
+    /// Close the counters of the previous frame and start a new one
+    pub fn begin_frame(&mut self) {
+        self.frames += 1;
+        self.max_draws_per_frame = self.max_draws_per_frame.max(self.draws_this_frame);
+        self.draws_this_frame = 0;
+        if self.is_waiting_this_frame {
+            self.key_wait_frames += 1;
+        }
+        self.is_waiting_this_frame = false;
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Number of times the instruction at an address ran
+    pub fn executions_at(&self, address: u16) -> u64 {
+        self.executions.get(address as usize).copied().unwrap_or(0)
+    }
+
+    /// Number of instructions that ran in a class, given by the first hex digit of the opcode
+    pub fn executions_of_class(&self, class: u8) -> u64 {
+        self.classes[(class & 0xF) as usize]
+    }
+
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+
+    pub fn max_draws_per_frame(&self) -> u64 {
+        self.max_draws_per_frame.max(self.draws_this_frame)
+    }
+
+    pub fn key_wait_frames(&self) -> u64 {
+        self.key_wait_frames + self.is_waiting_this_frame as u64
+    }
+
+    /// Addresses that ran at least once with their counts, most executed first
+    pub fn hot_spots(&self) -> Vec<(u16, u64)> {
+        let mut hot_spots: Vec<(u16, u64)> = self.executions
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(address, count)| (address as u16, *count))
+            .collect();
+        hot_spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot_spots
+    }
+
+    fn average_draws_per_frame(&self) -> f64 {
+        self.draws as f64 / self.frames.max(1) as f64
+    }
+
+    fn percentage(&self, count: u64) -> f64 {
+        100.0 * count as f64 / self.instructions.max(1) as f64
+    }
+
+    /// A report for people, with the most executed instructions disassembled from the machine's memory
+    pub fn text_report(&self, processor: &CHIP8) -> String {
+        let mut report: String = String::new();
+        writeln!(report, "Instructions: {} in {} frames", self.instructions, self.frames).unwrap();
+        writeln!(
+            report,
+            "Draws: {} ({:.2} per frame, at most {} in a frame)",
+            self.draws,
+            self.average_draws_per_frame(),
+            self.max_draws_per_frame()
+        ).unwrap();
+        writeln!(
+            report,
+            "Waiting for a key (Fx0A): {} instructions over {} frames ({:.2} s)",
+            self.key_wait_instructions,
+            self.key_wait_frames(),
+            self.key_wait_frames() as f64 / FRAMES_PER_SECOND
+        ).unwrap();
+
+        writeln!(report, "\nInstruction classes:").unwrap();
+        for (class, count) in self.classes.iter().enumerate().filter(|(_, count)| **count > 0) {
+            writeln!(report, "  {:X}xxx  {:>12}  {:>6.2}%", class, count, self.percentage(*count)).unwrap();
+        }
+
+        writeln!(report, "\nHot spots:").unwrap();
+        for (address, count) in self.hot_spots().into_iter().take(HOT_SPOT_COUNT) {
+            writeln!(
+                report,
+                "  {:#06X}  {:>12}  {:>6.2}%  {}",
+                address,
+                count,
+                self.percentage(count),
+                Debugger::describe_instruction(processor, address)
+            ).unwrap();
+        }
+        report
+    }
+
+    /// A report for tools, listing every executed address
+    pub fn json_report(&self, processor: &CHIP8) -> String {
+        let classes: Vec<String> = self.classes
+            .iter()
+            .enumerate()
+            .map(|(class, count)| format!("\"{:X}\": {}", class, count))
+            .collect();
+        let addresses: Vec<String> = self.hot_spots()
+            .into_iter()
+            .map(|(address, count)| {
+                format!(
+                    "    {{ \"address\": {}, \"count\": {}, \"instruction\": \"{}\" }}",
+                    address,
+                    count,
+                    json_escape(&Debugger::describe_instruction(processor, address))
+                )
+            })
+            .collect();
+
+        let mut report: String = String::new();
+        writeln!(report, "{{").unwrap();
+        writeln!(report, "  \"instructions\": {},", self.instructions).unwrap();
+        writeln!(report, "  \"frames\": {},", self.frames).unwrap();
+        writeln!(report, "  \"draws\": {},", self.draws).unwrap();
+        writeln!(report, "  \"average_draws_per_frame\": {:.4},", self.average_draws_per_frame()).unwrap();
+        writeln!(report, "  \"max_draws_per_frame\": {},", self.max_draws_per_frame()).unwrap();
+        writeln!(report, "  \"key_wait_instructions\": {},", self.key_wait_instructions).unwrap();
+        writeln!(report, "  \"key_wait_frames\": {},", self.key_wait_frames()).unwrap();
+        writeln!(report, "  \"classes\": {{ {} }},", classes.join(", ")).unwrap();
+        writeln!(report, "  \"addresses\": [\n{}\n  ]", addresses.join(",\n")).unwrap();
+        writeln!(report, "}}").unwrap();
+        report
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
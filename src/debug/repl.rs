@@ -0,0 +1,288 @@
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use super::debugger::{BreakReason, Debugger};
+use crate::chip8::cpu::CHIP8;
+
+const HELP: &str = "\
+Commands:
+  c, continue        Resume running
+  p, pause           Pause at the current instruction
+  s, step [n]        Execute n instructions (default 1)
+  n, next            Step over a CALL
+  f, finish          Run until the current subroutine returns
+  b, break <addr>    Add a breakpoint
+  d, delete <addr>   Remove a breakpoint
+  w, watch <addr>    Break when memory at addr is written
+  u, unwatch <addr>  Remove a watchpoint
+  l, list            List breakpoints and watchpoints
+  r, regs            Show registers, timers and the stack
+  stack              Show the stack
+  m, mem <addr> [n]  Dump n bytes of memory (default 16)
+  h, help            Show this help
+Addresses are hexadecimal, e.g. 2A0 or 0x2A0";
+
+/// A command entered in the debugger REPL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DebugCommand {
+    Continue,
+    Pause,
+    Step(usize),
+    Next,
+    Finish,
+    Break(u16),
+    Delete(u16),
+    Watch(u16),
+    Unwatch(u16),
+    List,
+    Registers,
+    Stack,
+    Memory(u16, u16),
+    Help,
+}
+
+impl FromStr for DebugCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command: &str = words.next().ok_or_else(|| String::from("Empty command"))?;
+        let argument: Option<&str> = words.next();
+
+        let address = || -> Result<u16, String> {
+            parse_address(argument.ok_or_else(|| format!("{} needs an address", command))?)
+        };
+
+        match command {
+            "c" | "continue" => Ok(DebugCommand::Continue),
+            "p" | "pause" => Ok(DebugCommand::Pause),
+            "s" | "step" => match argument {
+                Some(count) => count
+                    .parse()
+                    .map(DebugCommand::Step)
+                    .map_err(|_| format!("Invalid step count: {}", count)),
+                None => Ok(DebugCommand::Step(1)),
+            },
+            "n" | "next" => Ok(DebugCommand::Next),
+            "f" | "finish" => Ok(DebugCommand::Finish),
+            "b" | "break" => Ok(DebugCommand::Break(address()?)),
+            "d" | "delete" => Ok(DebugCommand::Delete(address()?)),
+            "w" | "watch" => Ok(DebugCommand::Watch(address()?)),
+            "u" | "unwatch" => Ok(DebugCommand::Unwatch(address()?)),
+            "l" | "list" => Ok(DebugCommand::List),
+            "r" | "regs" | "registers" => Ok(DebugCommand::Registers),
+            "stack" => Ok(DebugCommand::Stack),
+            "m" | "mem" | "memory" => {
+                let length: u16 = match words.next() {
+                    Some(length) => length.parse().map_err(|_| format!("Invalid length: {}", length))?,
+                    None => 16,
+                };
+                Ok(DebugCommand::Memory(address()?, length))
+            }
+            "h" | "help" | "?" => Ok(DebugCommand::Help),
+            _ => Err(format!("Unknown command: {}. Type help for a list of commands.", command)),
+        }
+    }
+}
+
+/// Parse a hexadecimal address, with or without a 0x prefix
+fn parse_address(text: &str) -> Result<u16, String> {
+    let digits: &str = text.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid address: {}", text))
+}
+
+/// Terminal REPL for the debugger.
+/// Lines are read from stdin on a separate thread so the frontend can keep rendering.
+pub struct Repl {
+    lines: Receiver<String>,
+}
+
+impl Repl {
+    /// Start reading commands from stdin
+    pub fn spawn() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Repl::prompt();
+        Repl { lines: receiver }
+    }
+
+    /// Run every command typed since the last call. Returns false once stdin has been closed.
+    pub fn poll(&self, debugger: &mut Debugger, processor: &mut CHIP8) -> bool {
+        loop {
+            match self.lines.try_recv() {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        let output: String = match line.parse::<DebugCommand>() {
+                            Ok(command) => Repl::execute(debugger, processor, command),
+                            Err(e) => e,
+                        };
+                        if !output.is_empty() {
+                            println!("{}", output.trim_end());
+                        }
+                    }
+                    Repl::prompt();
+                }
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    /// Report why the debugger paused the machine
+    pub fn report(reason: &BreakReason) {
+        println!();
+        println!("{}", reason);
+        Repl::prompt();
+    }
+
+    /// Run a single command and return its output
+    pub fn execute(debugger: &mut Debugger, processor: &mut CHIP8, command: DebugCommand) -> String {
+        match command {
+            DebugCommand::Continue => {
+                debugger.resume(processor);
+                String::from("Running")
+            }
+            DebugCommand::Pause => debugger.pause(processor).to_string(),
+            DebugCommand::Step(count) => {
+                let mut reason: BreakReason = debugger.step(processor);
+                for _i in 1..count {
+                    if let BreakReason::Watchpoint { .. } | BreakReason::Fault(_) = reason {
+                        break;
+                    }
+                    reason = debugger.step(processor);
+                }
+                format!("{}\n{}", reason, Debugger::describe_registers(processor))
+            }
+            DebugCommand::Next => match debugger.step_over(processor) {
+                Some(reason) => reason.to_string(),
+                None => String::from("Stepping over subroutine"),
+            },
+            DebugCommand::Finish => match debugger.run_to_return(processor) {
+                Ok(()) => String::from("Running until return"),
+                Err(e) => String::from(e),
+            },
+            DebugCommand::Break(address) => {
+                debugger.add_breakpoint(address);
+                format!("Breakpoint set at {:#05X}", address)
+            }
+            DebugCommand::Delete(address) => {
+                if debugger.remove_breakpoint(address) {
+                    format!("Breakpoint at {:#05X} removed", address)
+                } else {
+                    format!("No breakpoint at {:#05X}", address)
+                }
+            }
+            DebugCommand::Watch(address) => {
+                debugger.add_watchpoint(address);
+                format!("Watchpoint set at {:#05X}", address)
+            }
+            DebugCommand::Unwatch(address) => {
+                if debugger.remove_watchpoint(address) {
+                    format!("Watchpoint at {:#05X} removed", address)
+                } else {
+                    format!("No watchpoint at {:#05X}", address)
+                }
+            }
+            DebugCommand::List => {
+                let breakpoints: Vec<String> = debugger.breakpoints().map(|a| format!("{:#05X}", a)).collect();
+                let watchpoints: Vec<String> = debugger.watchpoints().map(|a| format!("{:#05X}", a)).collect();
+                format!("Breakpoints: {}\nWatchpoints: {}", breakpoints.join(", "), watchpoints.join(", "))
+            }
+            DebugCommand::Registers => Debugger::describe_registers(processor),
+            DebugCommand::Stack => Debugger::describe_stack(processor),
+            DebugCommand::Memory(address, length) => Debugger::describe_memory(processor, address, length),
+            DebugCommand::Help => String::from(HELP),
+        }
+    }
+
+    fn prompt() {
+        print!("(rc8) ");
+        io::stdout().flush().unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::quirks::QuirkProfile;
+
+    fn parse(line: &str) -> Result<DebugCommand, String> {
+        line.parse()
+    }
+
+    #[test]
+    fn commands_and_their_short_forms() {
+        for (long, short, command) in [
+            ("continue", "c", DebugCommand::Continue),
+            ("pause", "p", DebugCommand::Pause),
+            ("step", "s", DebugCommand::Step(1)),
+            ("next", "n", DebugCommand::Next),
+            ("finish", "f", DebugCommand::Finish),
+            ("list", "l", DebugCommand::List),
+            ("regs", "r", DebugCommand::Registers),
+            ("help", "h", DebugCommand::Help),
+        ] {
+            assert_eq!(parse(long), Ok(command.clone()));
+            assert_eq!(parse(short), Ok(command));
+        }
+        assert_eq!(parse("stack"), Ok(DebugCommand::Stack));
+        assert_eq!(parse("  registers  "), Ok(DebugCommand::Registers));
+    }
+
+    #[test]
+    fn commands_with_arguments() {
+        assert_eq!(parse("step 25"), Ok(DebugCommand::Step(25)));
+        assert_eq!(parse("b 2A0"), Ok(DebugCommand::Break(0x2A0)));
+        assert_eq!(parse("break 0x2a0"), Ok(DebugCommand::Break(0x2A0)));
+        assert_eq!(parse("d 0X300"), Ok(DebugCommand::Delete(0x300)));
+        assert_eq!(parse("watch FFFF"), Ok(DebugCommand::Watch(0xFFFF)));
+        assert_eq!(parse("u 300"), Ok(DebugCommand::Unwatch(0x300)));
+        assert_eq!(parse("mem 300"), Ok(DebugCommand::Memory(0x300, 16)));
+        assert_eq!(parse("m 300 64"), Ok(DebugCommand::Memory(0x300, 64)));
+    }
+
+    #[test]
+    fn invalid_commands() {
+        assert_eq!(parse(""), Err(String::from("Empty command")));
+        assert_eq!(parse("b"), Err(String::from("b needs an address")));
+        assert_eq!(parse("break 2G0"), Err(String::from("Invalid address: 2G0")));
+        assert_eq!(parse("watch 10000"), Err(String::from("Invalid address: 10000")));
+        assert_eq!(parse("step -1"), Err(String::from("Invalid step count: -1")));
+        assert_eq!(parse("mem 300 lots"), Err(String::from("Invalid length: lots")));
+        assert!(parse("jump 200").unwrap_err().starts_with("Unknown command: jump."));
+    }
+
+    #[test]
+    fn executed_commands_change_the_debugger() {
+        let mut processor: CHIP8 = CHIP8::new(QuirkProfile::CosmacVip.platform(), QuirkProfile::CosmacVip.quirks());
+        processor.load_rom_data(&[0x60, 0x01, 0x12, 0x02]).unwrap();
+        let mut debugger: Debugger = Debugger::new();
+
+        Repl::execute(&mut debugger, &mut processor, DebugCommand::Break(0x202));
+        Repl::execute(&mut debugger, &mut processor, DebugCommand::Watch(0x300));
+        let list: String = Repl::execute(&mut debugger, &mut processor, DebugCommand::List);
+        assert_eq!(list, "Breakpoints: 0x202\nWatchpoints: 0x300");
+
+        let step: String = Repl::execute(&mut debugger, &mut processor, DebugCommand::Step(1));
+        assert!(step.starts_with("Stepped to 0x202\nV0=0x01"));
+        assert_eq!(
+            Repl::execute(&mut debugger, &mut processor, DebugCommand::Delete(0x202)),
+            "Breakpoint at 0x202 removed"
+        );
+        assert_eq!(Repl::execute(&mut debugger, &mut processor, DebugCommand::Finish), "Not inside a subroutine");
+    }
+}
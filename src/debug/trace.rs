@@ -0,0 +1,143 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::error::Chip8Error;
+
+/// Which executed instructions are written to the trace
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    // Only instructions at addresses in this range
+    pub pc_range: Option<RangeInclusive<u16>>,
+    // Only instructions starting with one of these hex digits, e.g. 0xD for the draw instructions.
+    // Every instruction is traced when empty.
+    pub classes: Vec<u8>,
+}
+
+impl TraceFilter {
+    /// Whether the instruction at `pc` is traced
+    pub fn matches(&self, pc: u16, op_code: u16) -> bool {
+        let in_range: bool = self.pc_range.as_ref().is_none_or(|range| range.contains(&pc));
+        let class: u8 = (op_code >> 12) as u8;
+        in_range && (self.classes.is_empty() || self.classes.contains(&class))
+    }
+
+    /// Parse an inclusive range of hex addresses, e.g. `200-2FF`
+    pub fn parse_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+        let (start, end) = text
+            .split_once('-')
+            .ok_or_else(|| format!("Expected a range like 200-2FF, got {}", text))?;
+        let start: u16 = parse_hex(start)?;
+        let end: u16 = parse_hex(end)?;
+        if start > end {
+            return Err(format!("The range {} ends before it starts", text));
+        }
+        Ok(start..=end)
+    }
+
+    /// Parse a comma separated list of instruction classes, given by their first hex digit, e.g. `8,D`
+    pub fn parse_classes(text: &str) -> Result<Vec<u8>, String> {
+        text.split(',')
+            .map(|class| {
+                let class: &str = class.trim().trim_end_matches(['x', 'X']);
+                match u8::from_str_radix(class, 16) {
+                    Ok(digit) if class.len() == 1 => Ok(digit),
+                    _ => Err(format!("Instruction classes are a single hex digit, got {}", class)),
+                }
+            })
+            .collect()
+    }
+}
+
+fn parse_hex(text: &str) -> Result<u16, String> {
+    let digits: &str = text.trim().trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid address: {}", text))
+}
+
+/// Writes a line for every executed instruction the filter matches, e.g.
+/// `0x0204  0x8014  ADD V0, V1              I=0x0300  V0: 0x10 -> 0x30  VF: 0x01 -> 0x00`
+pub struct Tracer {
+    output: Box<dyn Write>,
+    filter: TraceFilter,
+    // The first write error, after which nothing else is written
+    error: Option<std::io::Error>,
+    lines: u64,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>, filter: TraceFilter) -> Self {
+        Tracer {
+            output,
+            filter,
+            error: None,
+            lines: 0,
+        }
+    }
+
+    /// Trace to a file, replacing it if it exists
+    pub fn create(path: &Path, filter: TraceFilter) -> Result<Self, Chip8Error> {
+        let file: File = File::create(path)?;
+        Ok(Tracer::new(Box::new(BufWriter::new(file)), filter))
+    }
+
+    pub fn filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    /// Write a traced instruction.
+    /// `description` is the address and mnemonic, the registers are given as they were before and after it ran.
+    pub fn record(&mut self, description: &str, registers_before: &[u8], registers_after: &[u8], i: u16) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let mut line: String = format!("{:<40}I={:#06X}", description, i);
+        for (index, (before, after)) in registers_before.iter().zip(registers_after).enumerate() {
+            if before != after {
+                write!(line, "  V{:X}: {:#04X} -> {:#04X}", index, before, after).unwrap();
+            }
+        }
+
+        match writeln!(self.output, "{}", line) {
+            Ok(()) => self.lines += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Flush the trace and return the number of lines written, or the first write error
+    pub fn finish(mut self) -> Result<u64, Chip8Error> {
+        if let Some(e) = self.error.take() {
+            return Err(Chip8Error::Io(e));
+        }
+        self.output.flush()?;
+        Ok(self.lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_by_pc_range_and_class() {
+        let filter: TraceFilter = TraceFilter {
+            pc_range: Some(TraceFilter::parse_range("0x200-20F").unwrap()),
+            classes: TraceFilter::parse_classes("8, Dx").unwrap(),
+        };
+        assert!(filter.matches(0x200, 0x8014));
+        assert!(filter.matches(0x20F, 0xD015));
+        assert!(!filter.matches(0x210, 0x8014));
+        assert!(!filter.matches(0x200, 0x6000));
+        assert!(TraceFilter::default().matches(0xFFF, 0x0000));
+    }
+
+    #[test]
+    fn invalid_filters() {
+        assert!(TraceFilter::parse_range("200").is_err());
+        assert!(TraceFilter::parse_range("2FF-200").is_err());
+        assert!(TraceFilter::parse_classes("8,DD").is_err());
+        assert!(TraceFilter::parse_classes("G").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use rusty_chip8::chip8::opcode_policy::OpcodePolicy;
+use rusty_chip8::chip8::quirks::QuirkProfile;
+use rusty_chip8::debug::debugger::Debugger;
+use rusty_chip8::error::Chip8Error;
+use crate::gui::filters::Filter;
+use crate::gui::palette::Theme;
+use crate::gui::persistence::Persistence;
+
+// Defaults used when neither the command line nor the configuration file set a value
+const DEFAULT_SCALE: u32 = 10;
+const DEFAULT_TONE: f32 = 240.0;
+const DEFAULT_REWIND_SECONDS: usize = 10;
+
+/// Settings that can be given on the command line, in the configuration file or for a single ROM.
+/// Unset values fall back to the next layer, see `Settings::overlay`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipf: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<u32>,
+    // Named set of colours: classic, green, amber, lcd, octo or custom
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    // Hex colours of the custom theme for the background, plane 1, plane 2 and both planes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Vec<String>>,
+    // How long erased pixels stay visible: off, decay or merge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persistence: Option<String>,
+    // Display filters: grid, scanlines, bloom and curvature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filters: Option<Vec<String>>,
+    // Frequency of the buzzer in Hz
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tone: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mute: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rewind_seconds: Option<usize>,
+    // What to do with instructions the platform doesn't support: ignore, halt or break
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unknown_opcodes: Option<String>,
+    // CHIP-8 key as a hex digit mapped to an SDL key name, e.g. "5" = "Up"
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Settings {
+    /// Combine two layers of settings, values set in `other` win
+    pub fn overlay(&self, other: &Settings) -> Settings {
+        let mut keys: BTreeMap<String, String> = self.keys.clone();
+        keys.extend(other.keys.clone());
+
+        Settings {
+            quirks: other.quirks.clone().or_else(|| self.quirks.clone()),
+            ipf: other.ipf.or(self.ipf),
+            scale: other.scale.or(self.scale),
+            theme: other.theme.clone().or_else(|| self.theme.clone()),
+            palette: other.palette.clone().or_else(|| self.palette.clone()),
+            persistence: other.persistence.clone().or_else(|| self.persistence.clone()),
+            filters: other.filters.clone().or_else(|| self.filters.clone()),
+            tone: other.tone.or(self.tone),
+            mute: other.mute.or(self.mute),
+            rewind_seconds: other.rewind_seconds.or(self.rewind_seconds),
+            unknown_opcodes: other.unknown_opcodes.clone().or_else(|| self.unknown_opcodes.clone()),
+            keys,
+        }
+    }
+
+    pub fn quirk_profile(&self) -> QuirkProfile {
+        match self.quirks.as_deref().map(str::parse) {
+            Some(Ok(profile)) => profile,
+            Some(Err(e)) => {
+                Debugger::dprint(format!("Ignoring quirks setting: {}", e), true);
+                QuirkProfile::default()
+            }
+            None => QuirkProfile::default(),
+        }
+    }
+
+    pub fn opcode_policy(&self) -> OpcodePolicy {
+        match self.unknown_opcodes.as_deref().map(str::parse) {
+            Some(Ok(policy)) => policy,
+            Some(Err(e)) => {
+                Debugger::dprint(format!("Ignoring unknown-opcodes setting: {}", e), true);
+                OpcodePolicy::default()
+            }
+            None => OpcodePolicy::default(),
+        }
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale.unwrap_or(DEFAULT_SCALE).max(1)
+    }
+
+    /// The theme, which is the custom one when only a palette is set
+    pub fn theme(&self) -> Theme {
+        match self.theme.as_deref().map(str::parse) {
+            Some(Ok(theme)) => theme,
+            Some(Err(e)) => {
+                Debugger::dprint(format!("Ignoring theme setting: {}", e), true);
+                Theme::default()
+            }
+            None if self.palette.is_some() => Theme::Custom,
+            None => Theme::default(),
+        }
+    }
+
+    /// The palette as RGB values, skipping colours that can't be parsed
+    pub fn palette(&self) -> Vec<u32> {
+        self.palette
+            .iter()
+            .flatten()
+            .filter_map(|colour| match parse_colour(colour) {
+                Ok(rgb) => Some(rgb),
+                Err(e) => {
+                    Debugger::dprint(format!("Ignoring palette colour: {}", e), true);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn persistence(&self) -> Persistence {
+        match self.persistence.as_deref().map(str::parse) {
+            Some(Ok(persistence)) => persistence,
+            Some(Err(e)) => {
+                Debugger::dprint(format!("Ignoring persistence setting: {}", e), true);
+                Persistence::default()
+            }
+            None => Persistence::default(),
+        }
+    }
+
+    /// The display filters in the order they run, skipping filters that aren't known
+    pub fn filters(&self) -> Vec<Filter> {
+        let mut filters: Vec<Filter> = self.filters
+            .iter()
+            .flatten()
+            .filter_map(|name| match name.parse() {
+                Ok(filter) => Some(filter),
+                Err(e) => {
+                    Debugger::dprint(format!("Ignoring filter {}: {}", name, e), true);
+                    None
+                }
+            })
+            .collect();
+        filters.sort();
+        filters.dedup();
+        filters
+    }
+
+    pub fn tone(&self) -> f32 {
+        self.tone.unwrap_or(DEFAULT_TONE)
+    }
+
+    pub fn mute(&self) -> bool {
+        self.mute.unwrap_or(false)
+    }
+
+    pub fn rewind_seconds(&self) -> usize {
+        self.rewind_seconds.unwrap_or(DEFAULT_REWIND_SECONDS)
+    }
+}
+
+/// Parse an RGB hex colour, e.g. `FFFFFF` or `#FFFFFF`
+pub fn parse_colour(colour: &str) -> Result<u32, String> {
+    let digits: &str = colour.trim().trim_start_matches('#');
+    if digits.len() != 6 {
+        return Err(format!("Invalid colour: {}", colour));
+    }
+    u32::from_str_radix(digits, 16).map_err(|_| format!("Invalid colour: {}", colour))
+}
+
+/// Directory the configuration file and other user files are kept in,
+///     e.g. `~/.config/rusty-chip8` on Linux
+pub fn config_directory() -> Option<PathBuf> {
+    dirs::config_dir().map(|directory| directory.join("rusty-chip8"))
+}
+
+/// Settings remembered for a single ROM
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct RomSettings {
+    // File name the ROM was last loaded from, to make the file easier to edit by hand
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(flatten)]
+    settings: Settings,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct ConfigFile {
+    defaults: Settings,
+    // Keyed by the SHA-1 of the ROM so settings follow a game when it is renamed or moved
+    roms: BTreeMap<String, RomSettings>,
+}
+
+/// Reads and writes the TOML configuration file,
+///     e.g. `~/.config/rusty-chip8/config.toml` on Linux
+pub struct ConfigDriver {
+    // None when the file couldn't be read, so a broken file is never overwritten
+    path: Option<PathBuf>,
+    config: ConfigFile,
+}
+
+impl ConfigDriver {
+    pub fn new() -> Result<Self, Chip8Error> {
+        let path: PathBuf = config_directory()
+            .ok_or_else(|| Chip8Error::Config(String::from("Could not find the configuration directory")))?
+            .join("config.toml");
+
+        let config: ConfigFile = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)
+                .map_err(|e| Chip8Error::Config(format!("Could not parse {}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ConfigFile::default(),
+            Err(e) => return Err(Chip8Error::Io(e)),
+        };
+
+        Ok(ConfigDriver {
+            path: Some(path),
+            config,
+        })
+    }
+
+    /// Configuration with only the built-in defaults, which is never saved
+    pub fn empty() -> Self {
+        ConfigDriver {
+            path: None,
+            config: ConfigFile::default(),
+        }
+    }
+
+    /// The global defaults
+    pub fn defaults(&self) -> &Settings {
+        &self.config.defaults
+    }
+
+    /// The settings remembered for a ROM
+    pub fn rom_settings(&self, rom_hash: &str) -> Settings {
+        self.config.roms
+            .get(rom_hash)
+            .map(|rom| rom.settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Remember settings for a ROM and write the configuration file
+    pub fn remember(&mut self, rom_hash: &str, name: &str, settings: &Settings) -> Result<(), Chip8Error> {
+        let rom: &mut RomSettings = self.config.roms.entry(rom_hash.to_string()).or_default();
+        let updated: Settings = rom.settings.overlay(settings);
+        if updated == rom.settings && rom.name.as_deref() == Some(name) {
+            return Ok(());
+        }
+        rom.settings = updated;
+        rom.name = Some(name.to_string());
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Chip8Error> {
+        let path: &PathBuf = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let text: String = toml::to_string_pretty(&self.config)
+            .map_err(|e| Chip8Error::Config(e.to_string()))?;
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        std::fs::write(path, text)?;
+        Debugger::dprint(format!("Saved configuration to {}", path.display()), false);
+        Ok(())
+    }
+}
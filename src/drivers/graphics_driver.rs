@@ -1,57 +1,278 @@
-use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
+use sdl2::{pixels::{Color, PixelFormatEnum}, rect::Rect, render::{Canvas, Texture, TextureAccess, TextureCreator}, video::{FullscreenType, Window, WindowContext}};
+use rusty_chip8::error::Chip8Error;
+use crate::gui::filters::{Filter, Image};
+use crate::gui::palette::Theme;
+use crate::gui::persistence::Persistence;
 
-pub struct GraphicsDriver {
-    vram: [u8; 2048],
-    canvas: Canvas<Window>
+// Width and height of the display in low resolution mode
+const LORES_WIDTH: u32 = 64;
+const LORES_HEIGHT: u32 = 32;
+
+// Width and height of the display in high resolution mode, the size of the texture
+const HIRES_WIDTH: u32 = 128;
+const HIRES_HEIGHT: u32 = 64;
+
+// The texture holds 8-bit red, green and blue values
+const BYTES_PER_PIXEL: usize = 3;
+
+// Share of the difference to its new colour an erased pixel keeps every frame with `Persistence::Decay`
+const PHOSPHOR_DECAY: f32 = 0.7;
+
+// Largest number of image pixels per display pixel filters work with, larger displays are scaled up by the renderer
+const MAX_FILTER_SCALE: u32 = 8;
+
+pub struct GraphicsDriver<'a> {
+    canvas: Canvas<Window>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    // The display is uploaded here once per frame and scaled to the window when copied to the canvas
+    texture: Texture<'a>,
+    // Filters run in order on an upscaled copy of the display, which is uploaded to its own texture
+    filters: Vec<Filter>,
+    filter_texture: Option<Texture<'a>>,
+    // The display as it was last drawn, to skip frames where nothing changed
+    frame: Vec<u8>,
+    row_length: usize,
+    is_dirty: bool,
+    persistence: Persistence,
+    // The pixels of the last frame, which `Persistence::Merge` combines with the next one
+    last_vram: Vec<u8>,
+    // The colour every pixel was last drawn in, which erased pixels fade from with `Persistence::Decay`
+    glow: Vec<[f32; 3]>,
+    // Set while erased pixels are still fading, so the display is redrawn even if the vram is unchanged
+    is_fading: bool,
+    // Background, then one colour for every combination of bitplanes
+    palette: Vec<Color>,
 }
 
-impl GraphicsDriver {
-    pub fn new(vram: [u8; 2048], canvas: Canvas<Window>) -> Result<Self, &'static str>{
+impl<'a> GraphicsDriver<'a> {
+    pub fn new(
+        canvas: Canvas<Window>,
+        texture_creator: &'a TextureCreator<WindowContext>
+    ) -> Result<Self, Chip8Error> {
+        let texture: Texture<'a> = texture_creator
+            .create_texture(PixelFormatEnum::RGB24, TextureAccess::Streaming, HIRES_WIDTH, HIRES_HEIGHT)
+            .map_err(|e| Chip8Error::Frontend(e.to_string()))?;
+
         Ok(GraphicsDriver {
-            vram,
-            canvas
+            canvas,
+            texture_creator,
+            texture,
+            filters: Vec::new(),
+            filter_texture: None,
+            frame: Vec::new(),
+            row_length: LORES_WIDTH as usize,
+            is_dirty: true,
+            persistence: Persistence::default(),
+            last_vram: Vec::new(),
+            glow: Vec::new(),
+            is_fading: false,
+            palette: rgb_colours(&Theme::default().colours(&[])),
         })
     }
 
-    /// Update the data in the vram
-    pub fn update_vram(&mut self, vram: [u8; 2048]) {
-        self.vram = vram
+    /// Size of the window needed to fit the display with low resolution pixels `scale` pixels wide
+    ///     and a margin of one pixel on every side
+    pub fn window_size(scale: u32) -> (u32, u32) {
+        ((LORES_WIDTH + 2) * scale, (LORES_HEIGHT + 2) * scale)
+    }
+
+    /// The window the canvas draws to
+    pub fn window_mut(&mut self) -> &mut Window {
+        self.canvas.window_mut()
+    }
+
+    /// Replace the colours with RGB values, in the order background, first bitplane,
+    ///     second bitplane and both bitplanes. Any further colours are used for further bitplane combinations.
+    pub fn set_palette(&mut self, colours: &[u32]) {
+        self.palette = rgb_colours(colours);
+        self.is_dirty = true;
+    }
+
+    /// Switch between a window and a borderless window covering the desktop
+    pub fn toggle_fullscreen(&mut self) -> Result<(), Chip8Error> {
+        let window: &mut Window = self.canvas.window_mut();
+        let fullscreen: FullscreenType = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(fullscreen).map_err(Chip8Error::Frontend)?;
+        self.is_dirty = true;
+        Ok(())
     }
 
-    /// Draw the vram data to a canvas
-    pub fn draw_to_screen(&mut self) {
-        // vram: [u8; 2048], canvas: &mut Canvas<Window>
-        let pixel_width: u32 = 10;
-        let pixel_height: u32 = 10;
-        let left_margin: u32 = 11;
-        let row_length: usize = 64;
-    
+    /// Change how long erased pixels stay visible
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.persistence = persistence;
+        self.last_vram.clear();
+        self.is_dirty = true;
+    }
+
+    /// Change the filters the display is drawn with, which run in the order they are given
+    pub fn set_filters(&mut self, filters: &[Filter]) {
+        self.filters = filters.to_vec();
+        self.is_dirty = true;
+    }
+
+    /// Draw the next frame even if the display hasn't changed, e.g. after the window was uncovered
+    pub fn invalidate(&mut self) {
+        self.is_dirty = true;
+    }
+
+    /// Update the data in the vram once per frame, marking the frame dirty if anything on screen changes.
+    /// The row length is 64 in low resolution mode and 128 in high resolution mode
+    pub fn update_vram(&mut self, vram: &[u8], row_length: usize) {
+        let cells: &[u8] = &vram[..row_length * (row_length / 2)];
+        let frame: Vec<u8> = match self.persistence {
+            Persistence::Merge if self.last_vram.len() == cells.len() => {
+                cells.iter().zip(&self.last_vram).map(|(pixel, last_pixel)| pixel | last_pixel).collect()
+            }
+            _ => cells.to_vec(),
+        };
+        self.last_vram.clear();
+        self.last_vram.extend_from_slice(cells);
+
+        if row_length != self.row_length || frame != self.frame || self.is_fading {
+            self.frame = frame;
+            self.row_length = row_length;
+            self.is_dirty = true;
+        }
+    }
+
+    /// Draw the vram data to a canvas, unless it is unchanged since the last frame
+    pub fn draw_to_screen(&mut self) -> Result<(), Chip8Error> {
+        if !self.is_dirty {
+            return Ok(());
+        }
+
+        let width: u32 = self.row_length as u32;
+        let height: u32 = width / 2;
+        self.update_glow();
+
+        let output_size: (u32, u32) = self.canvas.output_size().map_err(Chip8Error::Frontend)?;
+        let destination: Rect = GraphicsDriver::display_area(output_size, width, height);
+
         self.canvas.set_draw_color(Color::RGB(0, 0, 0));
         self.canvas.clear();
-    
-        for i in 0..self.vram.len() {
-            self.canvas.set_draw_color(self.get_color(self.vram[i]));
-            let x: u32 = (i % row_length) as u32;
-            let y: u32 = (i / row_length) as u32;
-            self.canvas.fill_rect(
-                Rect::new(
-                    ((left_margin + x) + (pixel_width * x)) as i32,
-                    ((left_margin + y) + (pixel_height * y)) as i32,
-                    pixel_width,
-                    pixel_height
-                )
-            ).unwrap();
+        if self.filters.is_empty() {
+            let source: Rect = Rect::new(0, 0, width, height);
+            upload(&mut self.texture, source, &self.glow, self.row_length)?;
+            self.canvas.copy(&self.texture, source, destination).map_err(Chip8Error::Frontend)?;
+        } else {
+            let cell_size: usize = (destination.width() / width).clamp(1, MAX_FILTER_SCALE) as usize;
+            let mut image: Image = Image::upscale(&self.glow, self.row_length, cell_size);
+            for filter in &self.filters {
+                filter.apply(&mut image, cell_size);
+            }
+
+            let texture: &mut Texture<'a> = sized_texture(
+                &mut self.filter_texture,
+                self.texture_creator,
+                image.width() as u32,
+                image.height() as u32
+            )?;
+            upload(texture, None, image.pixels(), image.width())?;
+            self.canvas.copy(texture, None, destination).map_err(Chip8Error::Frontend)?;
         }
         self.canvas.present();
+        self.is_dirty = false;
+        Ok(())
     }
-    
-    /// Get the color of the bit
-    /// White if on, dark grey if off
-    fn get_color(&self, pixel_bit: u8) -> Color {
-        if pixel_bit == 1 {
-            Color::RGB(255, 255, 255)
-        } else {
-            Color::RGB(33, 33, 33)
+
+    /// The largest area with whole pixels of the same size the display fits in, centred in the output.
+    /// The rest of the output is left black.
+    fn display_area((output_width, output_height): (u32, u32), width: u32, height: u32) -> Rect {
+        let pixel_size: u32 = (output_width / width).min(output_height / height).max(1);
+        let display_width: u32 = width * pixel_size;
+        let display_height: u32 = height * pixel_size;
+        Rect::new(
+            (output_width as i32 - display_width as i32) / 2,
+            (output_height as i32 - display_height as i32) / 2,
+            display_width,
+            display_height
+        )
+    }
+
+    /// Work out the colour every pixel is shown in.
+    /// Lit pixels always show their colour, erased pixels fade to the background with `Persistence::Decay`.
+    fn update_glow(&mut self) {
+        let is_decaying: bool = self.persistence == Persistence::Decay && self.glow.len() == self.frame.len();
+        if self.glow.len() != self.frame.len() {
+            self.glow = vec![[0.0; 3]; self.frame.len()];
+        }
+
+        self.is_fading = false;
+        for (glow, pixel_bits) in self.glow.iter_mut().zip(&self.frame) {
+            let colour: Color = GraphicsDriver::get_color(&self.palette, *pixel_bits);
+            let target: [f32; 3] = [colour.r as f32, colour.g as f32, colour.b as f32];
+            if !is_decaying || *pixel_bits != 0 {
+                *glow = target;
+                continue;
+            }
+
+            for (channel, target_channel) in glow.iter_mut().zip(target) {
+                *channel = target_channel + (*channel - target_channel) * PHOSPHOR_DECAY;
+                if (*channel - target_channel).abs() >= 0.5 {
+                    self.is_fading = true;
+                }
+            }
         }
     }
+
+    /// Get the color of the pixel
+    /// Dark grey if off, white if only the first bitplane is on by default.
+    /// XO-CHIP programs can also light up the second bitplane on its own or both together.
+    /// Combinations the palette has no colour for use its last colour.
+    fn get_color(palette: &[Color], pixel_bits: u8) -> Color {
+        palette
+            .get(pixel_bits as usize)
+            .or(palette.last())
+            .copied()
+            .unwrap_or(Color::RGB(0, 0, 0))
+    }
+}
+
+fn rgb_colours(colours: &[u32]) -> Vec<Color> {
+    colours
+        .iter()
+        .map(|rgb| {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            Color::RGB(r, g, b)
+        })
+        .collect()
+}
+
+/// Copy colours with `row_length` pixels per row into an area of a texture, or all of it
+fn upload(texture: &mut Texture, area: impl Into<Option<Rect>>, pixels: &[[f32; 3]], row_length: usize) -> Result<(), Chip8Error> {
+    texture
+        .with_lock(area, |buffer: &mut [u8], pitch: usize| {
+            for (row, colours) in pixels.chunks(row_length).enumerate() {
+                let line: &mut [u8] = &mut buffer[row * pitch..row * pitch + row_length * BYTES_PER_PIXEL];
+                for (texel, colour) in line.chunks_exact_mut(BYTES_PER_PIXEL).zip(colours) {
+                    for (channel, value) in texel.iter_mut().zip(colour) {
+                        *channel = value.round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+            }
+        })
+        .map_err(Chip8Error::Frontend)
+}
+
+/// The texture in a slot, created again if it isn't the given size
+fn sized_texture<'a, 'b>(
+    slot: &'b mut Option<Texture<'a>>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    width: u32,
+    height: u32
+) -> Result<&'b mut Texture<'a>, Chip8Error> {
+    let is_sized: bool = slot.as_ref().is_some_and(|texture| {
+        let query = texture.query();
+        query.width == width && query.height == height
+    });
+    if !is_sized {
+        let texture: Texture<'a> = texture_creator
+            .create_texture(PixelFormatEnum::RGB24, TextureAccess::Streaming, width, height)
+            .map_err(|e| Chip8Error::Frontend(e.to_string()))?;
+        *slot = Some(texture);
+    }
+    slot.as_mut().ok_or(Chip8Error::InvalidState("Filter texture missing"))
 }
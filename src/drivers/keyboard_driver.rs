@@ -1,32 +1,84 @@
+use std::collections::HashMap;
 use sdl2::keyboard::Keycode;
+use rusty_chip8::error::Chip8Error;
 
-pub struct KeyboardDriver { }
+pub struct KeyboardDriver {
+    keypad: HashMap<Keycode, u8>,
+}
 
 impl KeyboardDriver {
-    pub fn new() -> Result<Self, &'static str> {
-        Ok(KeyboardDriver{})
+    pub fn new() -> Result<Self, Chip8Error> {
+        // The 4x4 block of keys starting at 1 mirrors the layout of the COSMAC VIP keypad
+        let keypad: HashMap<Keycode, u8> = HashMap::from([
+            (Keycode::Num1, 0x1),
+            (Keycode::Num2, 0x2),
+            (Keycode::Num3, 0x3),
+            (Keycode::Num4, 0xC),
+            (Keycode::Q, 0x4),
+            (Keycode::W, 0x5),
+            (Keycode::E, 0x6),
+            (Keycode::R, 0xD),
+            (Keycode::A, 0x7),
+            (Keycode::S, 0x8),
+            (Keycode::D, 0x9),
+            (Keycode::F, 0xE),
+            (Keycode::Z, 0xA),
+            (Keycode::X, 0x0),
+            (Keycode::C, 0xB),
+            (Keycode::V, 0xF),
+        ]);
+
+        Ok(KeyboardDriver {
+            keypad
+        })
+    }
+
+    /// Bind a CHIP-8 key to a keyboard key given by its SDL name, e.g. "Up" or "Space".
+    /// The key the CHIP-8 key was bound to before is unbound.
+    pub fn bind(&mut self, chip8_key: u8, key_name: &str) -> Result<(), Chip8Error> {
+        if chip8_key > 0xF {
+            return Err(Chip8Error::Config(String::from("CHIP-8 keys go from 0 to F")));
+        }
+        let key: Keycode = Keycode::from_name(key_name)
+            .ok_or_else(|| Chip8Error::Config(format!("Unknown key name {}", key_name)))?;
+        self.keypad.retain(|_, bound| *bound != chip8_key);
+        self.keypad.insert(key, chip8_key);
+        Ok(())
     }
 
     /// Returns a mapped CHIP-8 key based on what keyboard key is tapped
     pub fn keyboard_to_keypad(&self, key: Keycode) -> Option<u8> {
+        self.keypad.get(&key).copied()
+    }
+
+    /// Returns the save state slot bound to a function key, F1 through F9
+    pub fn keyboard_to_state_slot(&self, key: Keycode) -> Option<u8> {
         match key {
-            Keycode::Num1 => Some(0x1),
-            Keycode::Num2 => Some(0x2),
-            Keycode::Num3 => Some(0x3),
-            Keycode::Num4 => Some(0xC),
-            Keycode::Q => Some(0x4),
-            Keycode::W => Some(0x5),
-            Keycode::E => Some(0x6),
-            Keycode::R => Some(0xD),
-            Keycode::A => Some(0x7),
-            Keycode::S => Some(0x8),
-            Keycode::D => Some(0x9),
-            Keycode::F => Some(0xE),
-            Keycode::Z => Some(0xA),
-            Keycode::X => Some(0x0),
-            Keycode::C => Some(0xB),
-            Keycode::V => Some(0xF),
+            Keycode::F1 => Some(1),
+            Keycode::F2 => Some(2),
+            Keycode::F3 => Some(3),
+            Keycode::F4 => Some(4),
+            Keycode::F5 => Some(5),
+            Keycode::F6 => Some(6),
+            Keycode::F7 => Some(7),
+            Keycode::F8 => Some(8),
+            Keycode::F9 => Some(9),
             _ => None,
         }
     }
+
+    /// Returns true for the key that rewinds the game while held
+    pub fn is_rewind_key(&self, key: Keycode) -> bool {
+        key == Keycode::Backspace
+    }
+
+    /// Whether the key switches to the next colour theme
+    pub fn is_theme_key(&self, key: Keycode) -> bool {
+        key == Keycode::F10
+    }
+
+    /// Whether the key switches between a window and fullscreen
+    pub fn is_fullscreen_key(&self, key: Keycode) -> bool {
+        key == Keycode::F11
+    }
 }
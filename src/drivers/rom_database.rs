@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use serde::Deserialize;
+use rusty_chip8::chip8::quirks::QuirkProfile;
+use rusty_chip8::debug::debugger::Debugger;
+use rusty_chip8::error::Chip8Error;
+use crate::drivers::config_driver::{config_directory, Settings};
+
+// Database compiled into the emulator. The repository only ships an empty list, copy programs.json
+//     from the community CHIP-8 database (https://github.com/chip-8/chip-8-database) here to fill it
+const BUNDLED_DATABASE: &str = include_str!("../../assets/database/programs.json");
+
+/// A program in the database, which can have several ROMs e.g. for different platforms or versions
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: BTreeMap<String, RomEntry>,
+}
+
+/// A single ROM, keyed by its SHA-1 in the database
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RomEntry {
+    // Platforms the ROM runs on, in order of preference
+    platforms: Vec<String>,
+    // Instructions per frame
+    tickrate: Option<u16>,
+    colors: Option<Colors>,
+    // Game input, e.g. "up", "a" or "player2Down", mapped to a CHIP-8 key
+    keys: BTreeMap<String, u8>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct Colors {
+    // Background first, then one colour for every combination of bitplanes
+    pixels: Vec<String>,
+}
+
+/// What the database knows about a ROM
+#[derive(Clone, Debug)]
+pub struct RomInfo {
+    pub title: String,
+    // Quirks, tickrate and colours for the ROM
+    pub settings: Settings,
+    // What the CHIP-8 keys the ROM uses do, e.g. ("up", 0x5)
+    pub key_hints: Vec<(String, u8)>,
+}
+
+impl RomInfo {
+    /// The key hints as text, e.g. `up: 5, down: 8`
+    pub fn describe_keys(&self) -> String {
+        let hints: Vec<String> = self.key_hints
+            .iter()
+            .map(|(action, key)| format!("{}: {:X}", action, key))
+            .collect();
+        hints.join(", ")
+    }
+}
+
+/// Database of known ROMs in the format of the community CHIP-8 database.
+/// A programs.json in the configuration directory replaces the copy bundled with the emulator.
+pub struct RomDatabase {
+    programs: Vec<Program>,
+}
+
+impl RomDatabase {
+    pub fn new() -> Result<Self, Chip8Error> {
+        let user_database: Option<PathBuf> = config_directory()
+            .map(|directory| directory.join("programs.json"))
+            .filter(|path| path.exists());
+
+        let text: String = match user_database {
+            Some(path) => {
+                Debugger::dprint(format!("Using ROM database {}", path.display()), false);
+                std::fs::read_to_string(path)?
+            }
+            None => String::from(BUNDLED_DATABASE),
+        };
+
+        RomDatabase::parse(&text)
+    }
+
+    /// Read a database from the text of a programs.json
+    fn parse(text: &str) -> Result<Self, Chip8Error> {
+        let programs: Vec<Program> = serde_json::from_str(text)
+            .map_err(|e| Chip8Error::Config(format!("Could not parse the ROM database: {}", e)))?;
+
+        Ok(RomDatabase {
+            programs
+        })
+    }
+
+    /// Look a ROM up by its SHA-1
+    pub fn lookup(&self, rom_hash: &str) -> Option<RomInfo> {
+        self.programs.iter().find_map(|program| {
+            let rom: &RomEntry = program.roms.get(rom_hash)?;
+            let settings: Settings = Settings {
+                quirks: rom.platforms
+                    .iter()
+                    .find_map(|platform| quirk_profile_for(platform))
+                    .map(|profile| profile.name().to_string()),
+                ipf: rom.tickrate,
+                palette: rom.colors.as_ref().map(|colors| colors.pixels.clone()).filter(|pixels| !pixels.is_empty()),
+                ..Settings::default()
+            };
+
+            Some(RomInfo {
+                title: program.title.clone(),
+                settings,
+                key_hints: rom.keys.iter().map(|(action, key)| (action.clone(), *key)).collect(),
+            })
+        })
+    }
+}
+
+/// The quirk profile matching a platform id of the database, if the platform is supported
+fn quirk_profile_for(platform: &str) -> Option<QuirkProfile> {
+    match platform {
+        "originalChip8" | "hybridVIP" => Some(QuirkProfile::CosmacVip),
+        "modernChip8" => Some(QuirkProfile::Modern),
+        "chip48" => Some(QuirkProfile::Chip48),
+        "superchip1" | "superchip" => Some(QuirkProfile::Schip11),
+        "xochip" => Some(QuirkProfile::XoChip),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::rom_driver::RomDriver;
+
+    // SHA-1 of the ROM 00E0 1200, which clears the screen and loops
+    const ROM_HASH: &str = "2cdd5bd3f4e30a4d56d9a8841ffcd5fbc2d0f735";
+
+    const DATABASE: &str = r##"[
+        {
+            "title": "Other program",
+            "roms": { "0000000000000000000000000000000000000000": { "platforms": ["originalChip8"] } }
+        },
+        {
+            "title": "Clear screen",
+            "authors": ["Nobody"],
+            "roms": {
+                "2cdd5bd3f4e30a4d56d9a8841ffcd5fbc2d0f735": {
+                    "file": "clear.ch8",
+                    "platforms": ["megachip8", "superchip", "xochip"],
+                    "tickrate": 30,
+                    "colors": { "pixels": ["#000000", "#ff8800"] },
+                    "keys": { "up": 5, "a": 6 }
+                }
+            }
+        }
+    ]"##;
+
+    #[test]
+    fn known_rom_gets_its_platform_and_settings() {
+        assert_eq!(RomDriver::hash(&[0x00, 0xE0, 0x12, 0x00]), ROM_HASH);
+
+        let database: RomDatabase = RomDatabase::parse(DATABASE).unwrap();
+        let info: RomInfo = database.lookup(ROM_HASH).unwrap();
+        assert_eq!(info.title, "Clear screen");
+        // Unsupported platforms are passed over for the first supported one
+        assert_eq!(info.settings.quirks.as_deref(), Some(QuirkProfile::Schip11.name()));
+        assert_eq!(info.settings.ipf, Some(30));
+        assert_eq!(info.settings.palette, Some(vec![String::from("#000000"), String::from("#ff8800")]));
+        assert_eq!(info.describe_keys(), "a: 6, up: 5");
+    }
+
+    #[test]
+    fn unknown_rom_is_not_found() {
+        let database: RomDatabase = RomDatabase::parse(DATABASE).unwrap();
+        assert!(database.lookup("2cdd5bd3f4e30a4d56d9a8841ffcd5fbc2d0f736").is_none());
+        assert!(RomDatabase::parse("{}").is_err());
+    }
+
+    #[test]
+    fn bundled_database_parses() {
+        assert!(RomDatabase::parse(BUNDLED_DATABASE).is_ok());
+    }
+}
@@ -1,30 +1,61 @@
+use std::fmt::Write;
 use std::path::PathBuf;
-use crate::debug::debugger::Debugger;
+use sha1::{Digest, Sha1};
+use rusty_chip8::debug::debugger::Debugger;
+use rusty_chip8::error::Chip8Error;
+use crate::drivers::rom_database::{RomDatabase, RomInfo};
 
 pub struct RomDriver {
     file_path: PathBuf
 }
 
 impl RomDriver {
-    pub fn new(file_path: PathBuf) -> Result<Self, &'static str> {
+    pub fn new(file_path: PathBuf) -> Result<Self, Chip8Error> {
         Ok(RomDriver {
             file_path
         })
     }
 
+    /// File name of the ROM, e.g. `pong.ch8`
+    pub fn file_name(&self) -> String {
+        self.file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// SHA-1 of the ROM data as lowercase hex, which identifies a ROM regardless of its file name
+    pub fn hash(data: &[u8]) -> String {
+        let mut hash: String = String::new();
+        for byte in Sha1::digest(data) {
+            write!(hash, "{:02x}", byte).unwrap();
+        }
+        hash
+    }
+
+    /// Look the ROM data up in the ROM database to find the settings it needs
+    pub fn identify(&self, data: &[u8]) -> Option<RomInfo> {
+        let database: RomDatabase = match RomDatabase::new() {
+            Ok(database) => database,
+            Err(e) => {
+                Debugger::dprint(e.to_string(), true);
+                return None;
+            }
+        };
+
+        let rom_hash: String = RomDriver::hash(data);
+        let info: Option<RomInfo> = database.lookup(&rom_hash);
+        match &info {
+            Some(info) => Debugger::dprint(format!("Identified ROM as {}", info.title), false),
+            None => Debugger::dprint(format!("ROM {} is not in the database", rom_hash), false),
+        }
+        info
+    }
+
     /// Reads a ROM file into memory
-    pub fn read_rom_data(&self) -> Result<Vec<u8>, std::io::Error> {
+    pub fn read_rom_data(&self) -> Result<Vec<u8>, Chip8Error> {
         if cfg!(debug_assertions) {
-            let fp: String = self.file_path
-                .clone()
-                .into_os_string()
-                .into_string()
-                .unwrap();
-    
-            let mut message: String = String::from("Opening file: ");
-            message.push_str(&fp);
-    
-            Debugger::dprint(message, false);
+            Debugger::dprint(format!("Opening file: {}", self.file_path.display()), false);
         }
     
         // Test code to read rom data
@@ -37,7 +68,7 @@ impl RomDriver {
                 if e.kind() == std::io::ErrorKind::PermissionDenied {
                     Debugger::dprint(String::from("Please run again with appropriate permissions."), true);
                 }
-                Err(e)
+                Err(Chip8Error::Io(e))
             }
         }
     }
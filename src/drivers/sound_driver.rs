@@ -1,13 +1,18 @@
 use sdl2::{AudioSubsystem, Sdl};
 use sdl2::audio::{AudioDevice, AudioCallback, AudioSpecDesired};
+use rusty_chip8::error::Chip8Error;
 
 pub struct SoundDriver {
-    audio: AudioDevice<SquareWave>
+    audio: AudioDevice<SquareWave>,
+    sample_rate: f32,
+    // Frequency of the square wave in Hz, played when there is no XO-CHIP audio pattern
+    tone: f32,
 }
 
 impl SoundDriver {
-    pub fn new(sdl: &Sdl) -> Result<Self, &'static str> {
-        let audio: AudioSubsystem = sdl.audio().unwrap();
+    /// Open the audio device, playing a square wave at `tone` Hz for the buzzer
+    pub fn new(sdl: &Sdl, tone: f32) -> Result<Self, Chip8Error> {
+        let audio: AudioSubsystem = sdl.audio().map_err(Chip8Error::Frontend)?;
     
         let spec: AudioSpecDesired = AudioSpecDesired {
             freq: Some(44100), //44.1 kHz audio
@@ -17,16 +22,40 @@ impl SoundDriver {
     
         let sc: AudioDevice<SquareWave> = audio.open_playback(None, &spec, |audio_spec| {
             SquareWave {
-                phase_inc: 240.0 / audio_spec.freq as f32,
+                phase_inc: tone / audio_spec.freq as f32,
                 phase: 0.0,
                 volume: 0.25,
+                pattern: None,
             }
-        }).unwrap();
+        }).map_err(Chip8Error::Frontend)?;
+        let sample_rate: f32 = sc.spec().freq as f32;
 
         Ok(SoundDriver {
-            audio: sc
+            audio: sc,
+            sample_rate,
+            tone,
         })
     }
+
+    /// Play an XO-CHIP audio pattern instead of the square wave, or the square wave if there is no pattern.
+    /// The 128 bits of the pattern are played back at `playback_rate` bits per second.
+    pub fn set_pattern(&mut self, pattern: Option<[u8; 16]>, playback_rate: f32) {
+        let mut wave = self.audio.lock();
+        if wave.pattern != pattern {
+            wave.pattern = pattern;
+            wave.phase = 0.0;
+        }
+        wave.phase_inc = match pattern {
+            Some(_) => playback_rate / 128.0 / self.sample_rate,
+            None => self.tone / self.sample_rate,
+        };
+    }
+
+    /// Play the pattern and pitch of a machine that was just restored from a state, from the start
+    pub fn restore(&mut self, pattern: Option<[u8; 16]>, playback_rate: f32) {
+        self.set_pattern(pattern, playback_rate);
+        self.audio.lock().phase = 0.0;
+    }
     
     /// Start the audio playback
     pub fn play_sound(&self) {
@@ -43,15 +72,24 @@ struct SquareWave {
     phase_inc: f32,
     phase: f32,
     volume: f32,
+    pattern: Option<[u8; 16]>,
 }
 
 impl AudioCallback for SquareWave {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
         for x in out.iter_mut() {
-            *x = self.volume * if self.phase < 0.5 { 1.0 } else { -1.0 };
+            let is_high: bool = match self.pattern {
+                // Play the bits of the XO-CHIP pattern buffer from most to least significant
+                Some(pattern) => {
+                    let bit: usize = (self.phase * 128.0) as usize % 128;
+                    pattern[bit / 8] & (0b1000_0000 >> (bit % 8)) != 0
+                }
+                // Generate a square wave
+                None => self.phase < 0.5,
+            };
+            *x = self.volume * if is_high { 1.0 } else { -1.0 };
             self.phase = (self.phase + self.phase_inc) % 1.0;
         }
     }
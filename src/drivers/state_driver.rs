@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+use rusty_chip8::debug::debugger::Debugger;
+use rusty_chip8::error::Chip8Error;
+
+pub struct StateDriver {
+    rom_path: PathBuf
+}
+
+impl StateDriver {
+    pub fn new(rom_path: PathBuf) -> Result<Self, Chip8Error> {
+        Ok(StateDriver {
+            rom_path
+        })
+    }
+
+    /// Get the path of a save state slot, stored next to the ROM
+    /// e.g. slot 1 of `pong.ch8` is `pong.state1`
+    pub fn slot_path(&self, slot: u8) -> PathBuf {
+        self.rom_path.with_extension(format!("state{}", slot))
+    }
+
+    /// Write a save state to a slot
+    pub fn save(&self, slot: u8, state: &[u8]) -> Result<(), std::io::Error> {
+        let path: PathBuf = self.slot_path(slot);
+        std::fs::write(&path, state)?;
+        Debugger::dprint(format!("Saved state to {}", path.display()), false);
+        Ok(())
+    }
+
+    /// Read a save state from a slot
+    pub fn load(&self, slot: u8) -> Result<Vec<u8>, std::io::Error> {
+        let path: PathBuf = self.slot_path(slot);
+        let state: Vec<u8> = std::fs::read(&path)?;
+        Debugger::dprint(format!("Loaded state from {}", path.display()), false);
+        Ok(state)
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+
+/// Errors raised by the interpreter and the frontend drivers.
+/// The CPU faults (stack overflow and underflow, invalid memory access, unknown opcodes and machine code routines)
+///     stop the machine at the faulting instruction, so it can be inspected or rewound.
+#[derive(Debug)]
+pub enum Chip8Error {
+    RomTooLarge { size: usize, capacity: usize },
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    InvalidMemoryAccess { pc: u16, address: usize },
+    UnknownOpcode { pc: u16, op_code: u16 },
+    // 0nnn called a COSMAC 1802 routine and no machine code hook is set
+    MachineCodeRoutine { pc: u16, address: u16 },
+    // A save state that can't be loaded
+    InvalidState(&'static str),
+    // A configuration file, ROM database or setting that can't be used
+    Config(String),
+    // SDL could not create a window, renderer or audio device
+    Frontend(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::RomTooLarge { size, capacity } => {
+                write!(f, "ROM is {} bytes but only {} bytes fit in memory", size, capacity)
+            }
+            Chip8Error::StackOverflow { pc } => write!(f, "Stack overflow at {:#05X}", pc),
+            Chip8Error::StackUnderflow { pc } => write!(f, "Return with an empty stack at {:#05X}", pc),
+            Chip8Error::InvalidMemoryAccess { pc, address } => {
+                write!(f, "Invalid memory access to {:#06X} at {:#05X}", address, pc)
+            }
+            Chip8Error::UnknownOpcode { pc, op_code } => write!(f, "Unknown opcode {:#06X} at {:#05X}", op_code, pc),
+            Chip8Error::MachineCodeRoutine { pc, address } => {
+                write!(f, "Machine code routine {:#05X} called at {:#05X}, which is not supported", address, pc)
+            }
+            Chip8Error::InvalidState(message) => write!(f, "{}", message),
+            Chip8Error::Config(message) => write!(f, "{}", message),
+            Chip8Error::Frontend(message) => write!(f, "{}", message),
+            Chip8Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Chip8Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Chip8Error {
+    fn from(e: std::io::Error) -> Self {
+        Chip8Error::Io(e)
+    }
+}
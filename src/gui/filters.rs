@@ -0,0 +1,359 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Brightness of the gap between pixels drawn by the pixel grid
+const GRID_BRIGHTNESS: f32 = 0.35;
+// Brightness of the dark half of every row of pixels drawn by the scanlines
+const SCANLINE_BRIGHTNESS: f32 = 0.6;
+// Share of the blurred image added back on top by the bloom
+const BLOOM_STRENGTH: f32 = 0.6;
+// How far the corners of the image are pulled in by the curvature
+const CURVATURE: f32 = 0.08;
+
+/// A post-processing step that makes the display look like a period screen.
+/// Filters run on the CPU on an upscaled copy of the display, in the order they are listed here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Filter {
+    /// Dark gaps between pixels like the cells of an LCD
+    PixelGrid,
+    /// Dark lines between the rows of pixels like the scanlines of a CRT
+    Scanlines,
+    /// A glow around bright pixels
+    Bloom,
+    /// Bends the image like the curved glass of a CRT
+    Curvature,
+}
+
+impl Filter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::PixelGrid => "grid",
+            Filter::Scanlines => "scanlines",
+            Filter::Bloom => "bloom",
+            Filter::Curvature => "curvature",
+        }
+    }
+
+    /// Parse a comma separated list of filters, e.g. `scanlines,bloom`.
+    /// The filters are sorted into the order they run in.
+    pub fn parse_list(text: &str) -> Result<Vec<Filter>, String> {
+        let mut filters: Vec<Filter> = text
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| name.parse().map_err(String::from))
+            .collect::<Result<_, _>>()?;
+        filters.sort();
+        filters.dedup();
+        Ok(filters)
+    }
+
+    /// Run the filter on an image with display pixels `cell_size` image pixels wide
+    pub fn apply(&self, image: &mut Image, cell_size: usize) {
+        match self {
+            Filter::PixelGrid => pixel_grid(image, cell_size),
+            Filter::Scanlines => scanlines(image, cell_size),
+            Filter::Bloom => bloom(image, cell_size),
+            Filter::Curvature => curvature(image),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Filter {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "grid" | "pixelgrid" => Ok(Filter::PixelGrid),
+            "scanlines" | "scanline" => Ok(Filter::Scanlines),
+            "bloom" | "glow" => Ok(Filter::Bloom),
+            "curvature" | "barrel" | "curve" => Ok(Filter::Curvature),
+            _ => Err("Unknown filter. Expected one of: grid, scanlines, bloom, curvature"),
+        }
+    }
+}
+
+/// RGB colours with channels from 0 to 255, which may go over 255 until the image is shown
+#[derive(Clone, Debug)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl Image {
+    /// Scale up an image of `width` pixels per row by a whole number
+    pub fn upscale(pixels: &[[f32; 3]], width: usize, factor: usize) -> Self {
+        let factor: usize = factor.max(1);
+        let height: usize = pixels.len() / width.max(1);
+        let mut scaled: Vec<[f32; 3]> = Vec::with_capacity(pixels.len() * factor * factor);
+        for row in pixels.chunks(width.max(1)) {
+            let start: usize = scaled.len();
+            for pixel in row {
+                scaled.extend(std::iter::repeat_n(*pixel, factor));
+            }
+            for _i in 1..factor {
+                scaled.extend_from_within(start..start + width * factor);
+            }
+        }
+
+        Image {
+            width: width * factor,
+            height: height * factor,
+            pixels: scaled,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[[f32; 3]] {
+        &self.pixels
+    }
+}
+
+fn darken(pixel: &mut [f32; 3], brightness: f32) {
+    for channel in pixel.iter_mut() {
+        *channel *= brightness;
+    }
+}
+
+/// Darken the last row and column of every display pixel
+fn pixel_grid(image: &mut Image, cell_size: usize) {
+    // Smaller cells would be all gap
+    if cell_size < 3 {
+        return;
+    }
+
+    let width: usize = image.width;
+    for (index, pixel) in image.pixels.iter_mut().enumerate() {
+        if index % width % cell_size == cell_size - 1 || index / width % cell_size == cell_size - 1 {
+            darken(pixel, GRID_BRIGHTNESS);
+        }
+    }
+}
+
+/// Darken the lower half of every row of display pixels, or every other line if they are one pixel high
+fn scanlines(image: &mut Image, cell_size: usize) {
+    let width: usize = image.width;
+    for (y, row) in image.pixels.chunks_mut(width).enumerate() {
+        let is_dark: bool = if cell_size < 2 { y % 2 == 1 } else { y % cell_size > (cell_size - 1) / 2 };
+        if is_dark {
+            row.iter_mut().for_each(|pixel| darken(pixel, SCANLINE_BRIGHTNESS));
+        }
+    }
+}
+
+/// Add a blurred copy of the image on top of it
+fn bloom(image: &mut Image, cell_size: usize) {
+    let radius: usize = cell_size.max(1);
+    let horizontal: Vec<[f32; 3]> = box_blur(&image.pixels, image.width, image.height, radius, 1, image.width);
+    let blurred: Vec<[f32; 3]> = box_blur(&horizontal, image.height, image.width, radius, image.width, 1);
+    for (pixel, glow) in image.pixels.iter_mut().zip(blurred) {
+        for (channel, glow_channel) in pixel.iter_mut().zip(glow) {
+            *channel += glow_channel * BLOOM_STRENGTH;
+        }
+    }
+}
+
+/// Average every pixel with the `radius` pixels on either side along one axis.
+/// The image is walked as `lines` lines of `length` pixels, where consecutive pixels of a line are `step` apart
+///     and consecutive lines start `line_step` apart.
+fn box_blur(
+    pixels: &[[f32; 3]],
+    length: usize,
+    lines: usize,
+    radius: usize,
+    step: usize,
+    line_step: usize
+) -> Vec<[f32; 3]> {
+    let mut blurred: Vec<[f32; 3]> = vec![[0.0; 3]; pixels.len()];
+    let window: f32 = (2 * radius + 1) as f32;
+    for line in 0..lines {
+        let start: usize = line * line_step;
+        let pixel = |position: usize| pixels[start + position * step];
+
+        // Running sum of the pixels in the window, which is clamped to the line
+        let mut sum: [f32; 3] = [0.0; 3];
+        for position in 0..=radius.min(length - 1) {
+            add(&mut sum, pixel(position), 1.0);
+        }
+        for position in 0..length {
+            blurred[start + position * step] = sum.map(|channel| channel / window);
+            if position + radius + 1 < length {
+                add(&mut sum, pixel(position + radius + 1), 1.0);
+            }
+            if position >= radius {
+                add(&mut sum, pixel(position - radius), -1.0);
+            }
+        }
+    }
+    blurred
+}
+
+fn add(sum: &mut [f32; 3], pixel: [f32; 3], sign: f32) {
+    for (channel, pixel_channel) in sum.iter_mut().zip(pixel) {
+        *channel += pixel_channel * sign;
+    }
+}
+
+/// Bend the image outwards from its centre, leaving the corners black
+fn curvature(image: &mut Image) {
+    let source: Vec<[f32; 3]> = image.pixels.clone();
+    let width: f32 = image.width as f32;
+    let height: f32 = image.height as f32;
+    for (index, pixel) in image.pixels.iter_mut().enumerate() {
+        // Position from -1 to 1 on both axes
+        let u: f32 = ((index % image.width) as f32 + 0.5) / width * 2.0 - 1.0;
+        let v: f32 = ((index / image.width) as f32 + 0.5) / height * 2.0 - 1.0;
+        let distortion: f32 = 1.0 + CURVATURE * (u * u + v * v);
+        let (source_u, source_v) = (u * distortion, v * distortion);
+
+        *pixel = if source_u.abs() > 1.0 || source_v.abs() > 1.0 {
+            [0.0; 3]
+        } else {
+            let x: usize = (((source_u + 1.0) / 2.0 * width) as usize).min(image.width - 1);
+            let y: usize = (((source_v + 1.0) / 2.0 * height) as usize).min(image.height - 1);
+            source[y * image.width + x]
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [f32; 3] = [255.0; 3];
+    const BLACK: [f32; 3] = [0.0; 3];
+
+    /// A white image of `width` by `height` display pixels, each `cell_size` image pixels wide
+    fn white_image(width: usize, height: usize, cell_size: usize) -> Image {
+        Image::upscale(&vec![WHITE; width * height], width, cell_size)
+    }
+
+    /// The image pixels the filter darkened
+    fn darkened(image: &Image) -> Vec<(usize, usize)> {
+        image.pixels()
+            .iter()
+            .enumerate()
+            .filter(|(_, pixel)| **pixel != WHITE)
+            .map(|(index, _)| (index % image.width(), index / image.width()))
+            .collect()
+    }
+
+    #[test]
+    fn upscale_repeats_every_pixel() {
+        let pixels: [[f32; 3]; 4] = [[1.0; 3], [2.0; 3], [3.0; 3], [4.0; 3]];
+        let image: Image = Image::upscale(&pixels, 2, 3);
+        assert_eq!((image.width(), image.height()), (6, 6));
+        assert_eq!(image.pixels().len(), 36);
+        for (index, pixel) in image.pixels().iter().enumerate() {
+            let (x, y) = (index % 6 / 3, index / 6 / 3);
+            assert_eq!(*pixel, pixels[y * 2 + x]);
+        }
+
+        let unscaled: Image = Image::upscale(&pixels, 2, 0);
+        assert_eq!((unscaled.width(), unscaled.height()), (2, 2));
+        assert_eq!(unscaled.pixels(), &pixels);
+    }
+
+    #[test]
+    fn blur_spreads_a_pixel_evenly() {
+        let mut pixels: Vec<[f32; 3]> = vec![BLACK; 11];
+        pixels[5] = [50.0, 100.0, 150.0];
+        let blurred: Vec<[f32; 3]> = box_blur(&pixels, 11, 1, 2, 1, 11);
+
+        for (index, pixel) in blurred.iter().enumerate() {
+            let expected: [f32; 3] = if (3..=7).contains(&index) { [10.0, 20.0, 30.0] } else { BLACK };
+            assert_eq!(*pixel, expected, "pixel {}", index);
+        }
+        let total: f32 = blurred.iter().map(|pixel| pixel[1]).sum();
+        assert!((total - 100.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn blur_runs_along_columns() {
+        // One column of a 3 by 5 image, walked with a step of a whole row
+        let mut pixels: Vec<[f32; 3]> = vec![BLACK; 15];
+        pixels[2 * 3 + 1] = [30.0; 3];
+        let blurred: Vec<[f32; 3]> = box_blur(&pixels, 5, 3, 1, 3, 1);
+        let lit: Vec<usize> = (0..15).filter(|index| blurred[*index] != BLACK).collect();
+        assert_eq!(lit, [4, 7, 10]);
+        assert_eq!(blurred[4], [10.0; 3]);
+    }
+
+    #[test]
+    fn grid_darkens_the_last_row_and_column_of_every_cell() {
+        let mut image: Image = white_image(2, 1, 4);
+        Filter::PixelGrid.apply(&mut image, 4);
+        let expected: Vec<(usize, usize)> = (0..8usize)
+            .flat_map(|x| (0..4usize).map(move |y| (x, y)))
+            .filter(|(x, y)| x % 4 == 3 || *y == 3)
+            .collect();
+        let mut pixels: Vec<(usize, usize)> = darkened(&image);
+        pixels.sort();
+        assert_eq!(pixels, expected);
+        assert_eq!(image.pixels()[3], [255.0 * GRID_BRIGHTNESS; 3]);
+
+        // Cells too small for a gap are left alone
+        let mut image: Image = white_image(2, 1, 2);
+        Filter::PixelGrid.apply(&mut image, 2);
+        assert!(darkened(&image).is_empty());
+    }
+
+    #[test]
+    fn scanlines_darken_the_lower_half_of_every_row() {
+        let mut image: Image = white_image(1, 2, 4);
+        Filter::Scanlines.apply(&mut image, 4);
+        let rows: Vec<usize> = (0..image.height())
+            .filter(|y| image.pixels()[y * image.width()] != WHITE)
+            .collect();
+        assert_eq!(rows, [2, 3, 6, 7]);
+        assert!(darkened(&image).iter().all(|(_, y)| rows.contains(y)));
+
+        // Odd cell sizes keep the larger half lit
+        let mut image: Image = white_image(1, 1, 3);
+        Filter::Scanlines.apply(&mut image, 3);
+        assert_eq!(darkened(&image), [(0, 2), (1, 2), (2, 2)]);
+
+        // Unscaled, every other line is dark
+        let mut image: Image = white_image(1, 4, 1);
+        Filter::Scanlines.apply(&mut image, 1);
+        assert_eq!(darkened(&image), [(0, 1), (0, 3)]);
+    }
+
+    #[test]
+    fn curvature_blacks_out_the_corners() {
+        let (width, height) = (64, 32);
+        let pixels: Vec<[f32; 3]> = (0..width * height).map(|index| [index as f32; 3]).collect();
+        let mut image: Image = Image::upscale(&pixels, width, 1);
+        Filter::Curvature.apply(&mut image, 1);
+
+        for (x, y) in [(0, 0), (width - 1, 0), (0, height - 1), (width - 1, height - 1)] {
+            assert_eq!(image.pixels()[y * width + x], BLACK, "corner {}, {}", x, y);
+        }
+        let centre: usize = height / 2 * width + width / 2;
+        assert_eq!(image.pixels()[centre], pixels[centre]);
+    }
+
+    #[test]
+    fn filter_lists_are_sorted_and_deduplicated() {
+        assert_eq!(
+            Filter::parse_list("curvature, bloom,scanlines,grid,bloom"),
+            Ok(vec![Filter::PixelGrid, Filter::Scanlines, Filter::Bloom, Filter::Curvature])
+        );
+        assert_eq!(Filter::parse_list("Pixel-Grid,,glow"), Ok(vec![Filter::PixelGrid, Filter::Bloom]));
+        assert_eq!(Filter::parse_list(""), Ok(Vec::new()));
+        assert!(Filter::parse_list("scanlines,blur").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Colours are listed as the background, then one colour for every combination of bitplanes
+const CLASSIC: [u32; 4] = [0x212121, 0xFFFFFF, 0xAAAAAA, 0x666666];
+const GREEN_PHOSPHOR: [u32; 4] = [0x0A1A0F, 0x33FF66, 0x1F9940, 0x99FFB3];
+const AMBER: [u32; 4] = [0x1A1000, 0xFFB000, 0x996A00, 0xFFD980];
+const LCD: [u32; 4] = [0x9BBC0F, 0x0F380F, 0x306230, 0x6B8C21];
+// The defaults of the Octo IDE
+const OCTO: [u32; 4] = [0x996600, 0xFFCC00, 0xFF6600, 0x662200];
+
+/// A set of display colours that can be picked by name
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    #[default]
+    Classic,
+    GreenPhosphor,
+    Amber,
+    Lcd,
+    Octo,
+    /// The colours of the `palette` setting, on top of the classic colours
+    Custom,
+}
+
+impl Theme {
+    /// Every theme in the order the palette hotkey cycles through them
+    pub const ALL: [Theme; 6] = [
+        Theme::Classic,
+        Theme::GreenPhosphor,
+        Theme::Amber,
+        Theme::Lcd,
+        Theme::Octo,
+        Theme::Custom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Classic => "classic",
+            Theme::GreenPhosphor => "green",
+            Theme::Amber => "amber",
+            Theme::Lcd => "lcd",
+            Theme::Octo => "octo",
+            Theme::Custom => "custom",
+        }
+    }
+
+    /// The theme after this one, wrapping around to the first
+    pub fn next(&self) -> Theme {
+        let index: usize = Theme::ALL.iter().position(|theme| theme == self).unwrap_or(0);
+        Theme::ALL[(index + 1) % Theme::ALL.len()]
+    }
+
+    /// The colours of the theme as RGB values, background first.
+    /// `custom` is the palette setting, which replaces the classic colours it covers for the custom theme
+    ///     and can list more than four colours.
+    pub fn colours(&self, custom: &[u32]) -> Vec<u32> {
+        match self {
+            Theme::Classic => CLASSIC.to_vec(),
+            Theme::GreenPhosphor => GREEN_PHOSPHOR.to_vec(),
+            Theme::Amber => AMBER.to_vec(),
+            Theme::Lcd => LCD.to_vec(),
+            Theme::Octo => OCTO.to_vec(),
+            Theme::Custom => {
+                let mut colours: Vec<u32> = custom.to_vec();
+                if colours.len() < CLASSIC.len() {
+                    colours.extend_from_slice(&CLASSIC[colours.len()..]);
+                }
+                colours
+            }
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Theme {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "classic" | "default" => Ok(Theme::Classic),
+            "green" | "greenphosphor" | "phosphor" => Ok(Theme::GreenPhosphor),
+            "amber" => Ok(Theme::Amber),
+            "lcd" => Ok(Theme::Lcd),
+            "octo" => Ok(Theme::Octo),
+            "custom" => Ok(Theme::Custom),
+            _ => Err("Unknown theme. Expected one of: classic, green, amber, lcd, octo, custom"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_names_and_aliases() {
+        for theme in Theme::ALL {
+            assert_eq!(theme.name().parse(), Ok(theme));
+            assert_eq!(theme.to_string(), theme.name());
+        }
+        assert_eq!("Green-Phosphor".parse(), Ok(Theme::GreenPhosphor));
+        assert_eq!("green_phosphor".parse(), Ok(Theme::GreenPhosphor));
+        assert_eq!("PHOSPHOR".parse(), Ok(Theme::GreenPhosphor));
+        assert_eq!("default".parse(), Ok(Theme::Classic));
+        assert!("purple".parse::<Theme>().is_err());
+    }
+
+    #[test]
+    fn next_cycles_through_every_theme() {
+        let mut theme: Theme = Theme::default();
+        for expected in Theme::ALL.iter().skip(1).chain(&[Theme::Classic]) {
+            theme = theme.next();
+            assert_eq!(theme, *expected);
+        }
+    }
+
+    #[test]
+    fn custom_colours_are_padded_with_the_classic_colours() {
+        assert_eq!(Theme::Custom.colours(&[]), CLASSIC);
+        assert_eq!(Theme::Custom.colours(&[0x000000, 0x00FF00]), [0x000000, 0x00FF00, CLASSIC[2], CLASSIC[3]]);
+        let many: [u32; 6] = [1, 2, 3, 4, 5, 6];
+        assert_eq!(Theme::Custom.colours(&many), many);
+        // Only the custom theme uses them
+        assert_eq!(Theme::Amber.colours(&many), AMBER);
+    }
+
+    #[test]
+    fn theme_colours_can_be_told_apart() {
+        for theme in Theme::ALL.iter().filter(|theme| **theme != Theme::Custom) {
+            let colours: Vec<u32> = theme.colours(&[]);
+            for (index, colour) in colours.iter().enumerate() {
+                for other in &colours[index + 1..] {
+                    let distance: u32 = colour.to_be_bytes()
+                        .iter()
+                        .zip(other.to_be_bytes())
+                        .map(|(a, b)| a.abs_diff(b) as u32)
+                        .sum();
+                    assert!(distance >= 64, "{} colours {:06X} and {:06X} look alike", theme, colour, other);
+                }
+            }
+        }
+    }
+}
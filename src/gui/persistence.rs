@@ -0,0 +1,64 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How long pixels stay lit after they are turned off, to hide the flicker of sprites
+///     that are erased and drawn again every frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Persistence {
+    /// Pixels turn off as soon as they are erased
+    #[default]
+    Off,
+    /// Erased pixels fade out over a few frames like the phosphor of a CRT
+    Decay,
+    /// Pixels lit in either of the last two frames are shown
+    Merge,
+}
+
+impl Persistence {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Persistence::Off => "off",
+            Persistence::Decay => "decay",
+            Persistence::Merge => "merge",
+        }
+    }
+}
+
+impl fmt::Display for Persistence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Persistence {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(Persistence::Off),
+            "decay" | "blend" | "phosphor" => Ok(Persistence::Decay),
+            "merge" | "or" => Ok(Persistence::Merge),
+            _ => Err("Unknown persistence. Expected one of: off, decay, merge"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_aliases() {
+        for persistence in [Persistence::Off, Persistence::Decay, Persistence::Merge] {
+            assert_eq!(persistence.name().parse(), Ok(persistence));
+            assert_eq!(persistence.to_string(), persistence.name());
+        }
+        assert_eq!("None".parse(), Ok(Persistence::Off));
+        assert_eq!("BLEND".parse(), Ok(Persistence::Decay));
+        assert_eq!("phosphor".parse(), Ok(Persistence::Decay));
+        assert_eq!("or".parse(), Ok(Persistence::Merge));
+        assert_eq!(Persistence::default(), Persistence::Off);
+        assert!("fade".parse::<Persistence>().is_err());
+        assert!("".parse::<Persistence>().is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use sdl2::{Sdl, VideoSubsystem};
 use sdl2::video::Window;
 use sdl2::EventPump;
+use rusty_chip8::error::Chip8Error;
 
 pub struct SDLWindow {
     pub window: Window,
@@ -13,18 +14,18 @@ impl SDLWindow {
         width: usize,
         height: usize,
         title: String,
-    ) -> Result<Self, &'static str> {
-        let video_subsystem: VideoSubsystem = sdl.video().unwrap();
+    ) -> Result<Self, Chip8Error> {
+        let video_subsystem: VideoSubsystem = sdl.video().map_err(Chip8Error::Frontend)?;
 
         // Initiate the window
         let window: Window = video_subsystem
             .window(&title, width as u32, height as u32)
             .position_centered()
-            // .resizable()
+            .resizable()
             .build()
-            .unwrap();
+            .map_err(|e| Chip8Error::Frontend(e.to_string()))?;
 
-        let event_pump: sdl2::EventPump =  sdl.event_pump().unwrap();
+        let event_pump: sdl2::EventPump =  sdl.event_pump().map_err(Chip8Error::Frontend)?;
 
         Ok(SDLWindow {
             window,
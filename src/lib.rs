@@ -0,0 +1,30 @@
+//! The headless CHIP-8 interpreter core.
+//! It has no SDL dependency, so it can be driven without a window.
+
+pub mod asm {
+    pub mod assembler;
+    pub mod disassembler;
+    pub mod instruction;
+}
+
+pub mod chip8 {
+    pub mod cpu;
+    pub mod font;
+    pub mod headless;
+    pub mod op_code;
+    pub mod opcode_policy;
+    pub mod platform;
+    pub mod quirks;
+    pub mod rewind;
+    pub mod rng;
+    pub mod state;
+}
+
+pub mod debug {
+    pub mod debugger;
+    pub mod profiler;
+    pub mod repl;
+    pub mod trace;
+}
+
+pub mod error;
@@ -1,113 +1,530 @@
-#![windows_subsystem = "windows"]
+// Release builds on Windows don't open a console window, see `attach_console` for how CLI output still works
+#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod gui {
+    pub mod filters;
+    pub mod palette;
+    pub mod persistence;
     pub mod windows {
         pub mod base_window;
     }
 }
 
+mod cli {
+    pub mod arguments;
+}
+
 mod drivers {
+    pub mod config_driver;
+    pub mod rom_database;
     pub mod rom_driver;
     pub mod graphics_driver;
     pub mod keyboard_driver;
     pub mod sound_driver;
+    pub mod state_driver;
 }
 
-mod chip8 {
-    pub mod cpu;
-    pub mod font;
-    pub mod op_code;
-}
-
-pub mod debug {
-    pub mod debugger;
-}
-
-use chip8::cpu::CHIP8;
+use rusty_chip8::asm::assembler::Assembler;
+use rusty_chip8::asm::disassembler::Disassembler;
+use rusty_chip8::chip8::cpu::CHIP8;
+use rusty_chip8::chip8::headless::HeadlessRunner;
+use rusty_chip8::chip8::opcode_policy::OpcodePolicy;
+use rusty_chip8::chip8::quirks::QuirkProfile;
+use rusty_chip8::chip8::rewind::RewindBuffer;
+use rusty_chip8::chip8::rng::Chip8Rng;
+use rusty_chip8::debug::debugger::{BreakReason, Debugger};
+use rusty_chip8::debug::repl::Repl;
+use rusty_chip8::debug::trace::Tracer;
+use rusty_chip8::error::Chip8Error;
+use cli::arguments::{Arguments, Command, USAGE};
+use drivers::config_driver::{ConfigDriver, Settings};
 use drivers::graphics_driver::GraphicsDriver;
+use drivers::rom_database::RomInfo;
 use drivers::keyboard_driver::KeyboardDriver;
 use drivers::rom_driver::RomDriver;
 use drivers::sound_driver::SoundDriver;
+use drivers::state_driver::StateDriver;
+use gui::palette::Theme;
 use gui::windows::base_window::SDLWindow;
 use native_dialog::FileDialog;
 use sdl2::Sdl;
-use sdl2::event::Event;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Mod;
+use sdl2::render::{Canvas, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use std::fs;
 use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Timers and the display run at 60 Hz
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Save the machine state to a slot, or restore it from one
+fn handle_state_slot(processor: &mut CHIP8, state_driver: &StateDriver, slot: u8, is_save: bool) {
+    if is_save {
+        if let Err(e) = state_driver.save(slot, &processor.save_state()) {
+            Debugger::dprint(format!("Could not save state: {}", e), true);
+        }
+        return;
+    }
+
+    match state_driver.load(slot) {
+        Ok(state) => {
+            if let Err(e) = processor.load_state(&state) {
+                Debugger::dprint(format!("Could not load state: {}", e), true);
+            }
+        }
+        Err(e) => Debugger::dprint(format!("Could not load state: {}", e), true),
+    }
+}
+
+/// Switch the sound to the audio pattern and pitch of a machine that was just loaded from a state
+fn restore_sound(sound_driver: &mut Option<SoundDriver>, processor: &CHIP8) {
+    if let Some(sound_driver) = sound_driver.as_mut() {
+        sound_driver.restore(processor.audio_pattern(), processor.audio_playback_rate());
+    }
+}
+
+/// Print the disassembly of a ROM, for the `disasm` subcommand
+fn disassemble_rom(path: Option<PathBuf>, quirk_profile: QuirkProfile) {
+    let path: PathBuf = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: RustyCHIP8 disasm <rom> [--quirks <profile>]");
+            return;
+        }
+    };
+
+    let rom_driver: RomDriver = match RomDriver::new(path) {
+        Ok(rom_driver) => rom_driver,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    match rom_driver.read_rom_data() {
+        Ok(rom) => print!("{}", Disassembler::new(&rom, quirk_profile.platform()).to_source()),
+        Err(e) => eprintln!("Could not read ROM: {}", e),
+    }
+}
+
+/// Assemble a source file into a ROM, for the `asm` subcommand.
+/// The ROM is written next to the source with a .ch8 extension unless `-o` is given.
+fn assemble_rom(path: Option<PathBuf>, output: Option<PathBuf>) {
+    let path: PathBuf = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: RustyCHIP8 asm <source> [-o <rom>]");
+            return;
+        }
+    };
+    let output: PathBuf = output.unwrap_or_else(|| path.with_extension("ch8"));
+
+    match Assembler::assemble_file(&path) {
+        Ok(rom) => match std::fs::write(&output, &rom) {
+            Ok(()) => println!("Wrote {} bytes to {}", rom.len(), output.display()),
+            Err(e) => eprintln!("Could not write {}: {}", output.display(), e),
+        },
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// Create a machine with the given settings and load a ROM into it
+fn create_processor(settings: &Settings, seed: Option<u64>, rom: &[u8]) -> Result<CHIP8, Chip8Error> {
+    let rng: Chip8Rng = match seed {
+        Some(seed) => Chip8Rng::new(seed),
+        None => Chip8Rng::from_entropy(),
+    };
+
+    let quirk_profile: QuirkProfile = settings.quirk_profile();
+    let mut processor: CHIP8 = CHIP8::new(quirk_profile.platform(), quirk_profile.quirks());
+    Debugger::dprint(format!("RNG seed: {}", rng.seed()), false);
+    processor.set_rng(rng);
+    processor.set_opcode_policy(settings.opcode_policy());
+    if let Some(instructions_per_frame) = settings.ipf {
+        processor.set_instructions_per_frame(instructions_per_frame);
+    }
+
+    processor.load_rom_data(rom)?;
+    Ok(processor)
+}
+
+/// Start tracing executed instructions if a trace file was given
+fn start_trace(processor: &mut CHIP8, arguments: &Arguments) -> Result<(), Chip8Error> {
+    if let Some(path) = &arguments.trace {
+        processor.set_tracer(Some(Tracer::create(path, arguments.trace_filter.clone())?));
+    }
+    Ok(())
+}
+
+/// Flush the trace file, if instructions were being traced
+fn finish_trace(processor: &mut CHIP8) -> Result<(), Chip8Error> {
+    if let Some(tracer) = processor.take_tracer() {
+        let lines: u64 = tracer.finish()?;
+        Debugger::dprint(format!("Traced {} instructions", lines), false);
+    }
+    Ok(())
+}
+
+/// Write the profile of the run if one was asked for, as JSON if the file name ends in .json
+fn finish_profile(processor: &mut CHIP8, arguments: &Arguments) -> Result<(), Chip8Error> {
+    if let (Some(path), Some(profile)) = (&arguments.profile, processor.take_profile()) {
+        let report: String = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => profile.json_report(processor),
+            _ => profile.text_report(processor),
+        };
+        fs::write(path, report)?;
+    }
+    Ok(())
+}
+
+/// Read a ROM and work out its settings from the configuration file, the ROM database and the command line.
+/// Settings given on the command line are remembered for the ROM, unless it runs headless.
+fn load_rom(
+    path: PathBuf,
+    arguments: &Arguments,
+    config_driver: &mut ConfigDriver
+) -> Result<(Vec<u8>, Settings, Option<RomInfo>), Chip8Error> {
+    let rom_driver: RomDriver = RomDriver::new(path)?;
+    let rom: Vec<u8> = rom_driver.read_rom_data()?;
+
+    let rom_hash: String = RomDriver::hash(&rom);
+    let remembered: Settings = arguments.remembered_settings();
+    // Headless runs are scripted, e.g. by tests, and shouldn't change how the ROM runs in the window
+    if remembered != Settings::default() && !arguments.headless {
+        if let Err(e) = config_driver.remember(&rom_hash, &rom_driver.file_name(), &remembered) {
+            Debugger::dprint(format!("Could not save configuration: {}", e), true);
+        }
+    }
+
+    let info: Option<RomInfo> = rom_driver.identify(&rom);
+    let known_settings: Settings = info.as_ref().map(|info| info.settings.clone()).unwrap_or_default();
+    let settings: Settings = config_driver.defaults()
+        .overlay(&known_settings)
+        .overlay(&config_driver.rom_settings(&rom_hash))
+        .overlay(&arguments.settings());
+    Ok((rom, settings, info))
+}
+
+/// Load the configuration file, falling back to the defaults if it can't be read
+fn load_config() -> ConfigDriver {
+    ConfigDriver::new().unwrap_or_else(|e| {
+        eprintln!("{}, using the default settings", e);
+        ConfigDriver::empty()
+    })
+}
+
+/// Run a ROM without a window or sound until it exits or the frame limit is reached,
+///     then print the display as text
+fn run_headless(arguments: &Arguments) -> Result<(), Chip8Error> {
+    let path: PathBuf = arguments.path
+        .clone()
+        .ok_or_else(|| Chip8Error::Config(String::from("--headless needs a ROM")))?;
+
+    let mut config_driver: ConfigDriver = load_config();
+    let (rom, settings, _info) = load_rom(path, arguments, &mut config_driver)?;
+    let mut processor: CHIP8 = create_processor(&settings, arguments.seed, &rom)?;
+    start_trace(&mut processor, arguments)?;
+    if arguments.profile.is_some() {
+        processor.start_profiling();
+    }
+    let mut runner: HeadlessRunner = HeadlessRunner::new(processor);
+    let result: Result<(), Chip8Error> = runner.run(arguments.frames);
+    finish_trace(runner.processor_mut())?;
+    finish_profile(runner.processor_mut(), arguments)?;
+
+    // The display is still printed on a fault so the state of the machine can be seen
+    print!("{}", runner.screenshot());
+    result
+}
+
+/// Windows release builds use the GUI subsystem, so they have no console of their own.
+/// When started from a terminal, attach to it so help, errors, headless output and the subcommands are shown.
+#[cfg(all(windows, not(debug_assertions)))]
+fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+
+    // This fails when started from Explorer, which has no console to attach to
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(all(windows, not(debug_assertions))))]
+fn attach_console() {}
 
 fn main(){
+    attach_console();
+
+    let arguments: Arguments = match Arguments::from_env() {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let result: Result<(), Chip8Error> = match arguments.command.clone() {
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::Disassemble => {
+            disassemble_rom(arguments.path, arguments.quirks.unwrap_or_default());
+            Ok(())
+        }
+        Command::Assemble { output } => {
+            assemble_rom(arguments.path, output);
+            Ok(())
+        }
+        Command::Run if arguments.headless => run_headless(&arguments),
+        Command::Run => run(arguments),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Run a ROM in a window
+fn run(arguments: Arguments) -> Result<(), Chip8Error> {
+    let mut config_driver: ConfigDriver = load_config();
+
     // We need to declare SDL first before the file dialog
     // This way we can capture keystrokes
-    let sdl: Sdl = sdl2::init().unwrap();
+    let sdl: Sdl = sdl2::init().map_err(Chip8Error::Frontend)?;
     let main_window_title: String = String::from("Rusty CHIP-8");
+    let scale: u32 = config_driver.defaults().overlay(&arguments.settings()).scale();
+    let (window_width, window_height) = GraphicsDriver::window_size(scale);
     let mut window: SDLWindow = SDLWindow::new(
         &sdl,
-        725,
-        375,
+        window_width as usize,
+        window_height as usize,
         main_window_title
-    ).unwrap();
+    )?;
 
-    let path: Option<PathBuf> = FileDialog::new()
-        .set_location("~/Desktop")
-        .add_filter("CHIP-8 ROM", &["ch8"])
-        .show_open_single_file()
-        .unwrap();
+    // Only ask for a ROM if none was given on the command line
+    let path: Option<PathBuf> = match arguments.path.clone() {
+        Some(path) => Some(path),
+        None => FileDialog::new()
+            .set_location("~/Desktop")
+            .add_filter("CHIP-8 ROM", &["ch8", "sc8", "xo8"])
+            .show_open_single_file()
+            .map_err(|e| Chip8Error::Frontend(e.to_string()))?,
+    };
 
     let path: PathBuf = match path {
         Some(path) => path,
-        None => return,
+        None => return Ok(()),
+    };
+
+    // Load rom into memory
+    let (rom, settings, info) = load_rom(path.clone(), &arguments, &mut config_driver)?;
+    let rom_hash: String = RomDriver::hash(&rom);
+    let rom_name: String = RomDriver::new(path.clone())?.file_name();
+    let mut processor: CHIP8 = create_processor(&settings, arguments.seed, &rom)?;
+    start_trace(&mut processor, &arguments)?;
+    if arguments.profile.is_some() {
+        processor.start_profiling();
+    }
+
+    // Show what the ROM database knows about the game
+    let title: String = match &info {
+        Some(info) => match info.describe_keys().as_str() {
+            "" => format!("Rusty CHIP-8 - {}", info.title),
+            keys => format!("Rusty CHIP-8 - {} ({})", info.title, keys),
+        },
+        None => String::from("Rusty CHIP-8"),
     };
+    window.window.set_title(&title).map_err(|e| Chip8Error::Frontend(e.to_string()))?;
+
+    // The ROM can have its own scale
+    if settings.scale() != scale {
+        let (window_width, window_height) = GraphicsDriver::window_size(settings.scale());
+        window.window.set_size(window_width, window_height).map_err(|e| Chip8Error::Frontend(e.to_string()))?;
+    }
 
     // Create canvas
     let canvas : Canvas<Window> = window.window.into_canvas()
         // .present_vsync()
         .build()
-        .unwrap();
+        .map_err(|e| Chip8Error::Frontend(e.to_string()))?;
 
-    // Load rom into memory
-    let rom_driver: RomDriver = RomDriver::new(path).unwrap();
-    let buffer: Vec<u8> = match rom_driver.read_rom_data() {
-        Ok(path) => path,
-        Err(e) => panic!("{}", e)
+    // Create Drivers
+    let mut keyboard_driver: KeyboardDriver = KeyboardDriver::new()?;
+    for (chip8_key, key_name) in &settings.keys {
+        let result: Result<(), Chip8Error> = match u8::from_str_radix(chip8_key, 16) {
+            Ok(chip8_key) => keyboard_driver.bind(chip8_key, key_name),
+            Err(_) => Err(Chip8Error::Config(String::from("CHIP-8 keys go from 0 to F"))),
+        };
+        if let Err(e) = result {
+            Debugger::dprint(format!("Ignoring key binding {} = {}: {}", chip8_key, key_name, e), true);
+        }
+    }
+    let texture_creator: TextureCreator<WindowContext> = canvas.texture_creator();
+    let mut graphics_driver: GraphicsDriver = GraphicsDriver::new(canvas, &texture_creator)?;
+    let mut theme: Theme = settings.theme();
+    graphics_driver.set_palette(&theme.colours(&settings.palette()));
+    graphics_driver.set_persistence(settings.persistence());
+    graphics_driver.set_filters(&settings.filters());
+    let mut sound_driver: Option<SoundDriver> = if settings.mute() {
+        None
+    } else {
+        Some(SoundDriver::new(&sdl, settings.tone())?)
     };
+    let state_driver: StateDriver = StateDriver::new(path)?;
 
-    let mut processor: CHIP8 = CHIP8::new();
-
-    // Create Drivers
-    let keyboard_driver: KeyboardDriver = KeyboardDriver::new().unwrap();
-    let mut graphics_driver: GraphicsDriver = GraphicsDriver::new(processor.vram, canvas).unwrap();
-    let sound_driver: SoundDriver = SoundDriver::new(&sdl).unwrap();
+    let mut rewind_buffer: RewindBuffer = RewindBuffer::with_seconds(settings.rewind_seconds());
+    let mut is_rewinding: bool = false;
+    // Set when an instruction faults, the machine stays stopped until it is rewound
+    let mut fault: Option<Chip8Error> = None;
 
-    processor.load_rom_data(&buffer);
+    // The debugger is controlled from a terminal REPL while the window keeps rendering
+    let mut debugger: Option<(Debugger, Repl)> = if arguments.debug {
+        println!("Debugger enabled. Type help for a list of commands.");
+        Some((Debugger::new(), Repl::spawn()))
+    } else {
+        None
+    };
 
     // Start the SDL2 application
+    let mut next_frame: Instant = Instant::now();
     'gameloop: loop {
         for event in window.event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'gameloop,
-                Event::KeyDown { keycode: Some(key ), ..} => {
-                    if let Some(k) = keyboard_driver.keyboard_to_keypad(key) {
+                Event::KeyDown { keycode: Some(key ), keymod, repeat, ..} => {
+                    if let Some(slot) = keyboard_driver.keyboard_to_state_slot(key) {
+                        // F1-F9 load a save state slot, Shift+F1-F9 save to it
+                        if !repeat {
+                            let is_save: bool = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                            handle_state_slot(&mut processor, &state_driver, slot, is_save);
+                            if !is_save {
+                                restore_sound(&mut sound_driver, &processor);
+                            }
+                        }
+                    } else if keyboard_driver.is_rewind_key(key) {
+                        is_rewinding = true;
+                    } else if keyboard_driver.is_theme_key(key) {
+                        if !repeat {
+                            // Switch to the next theme and remember it for the ROM
+                            theme = theme.next();
+                            graphics_driver.set_palette(&theme.colours(&settings.palette()));
+                            let remembered: Settings = Settings {
+                                theme: Some(theme.name().to_string()),
+                                ..Settings::default()
+                            };
+                            if let Err(e) = config_driver.remember(&rom_hash, &rom_name, &remembered) {
+                                Debugger::dprint(format!("Could not save configuration: {}", e), true);
+                            }
+                        }
+                    } else if keyboard_driver.is_fullscreen_key(key) {
+                        if !repeat {
+                            graphics_driver.toggle_fullscreen()?;
+                        }
+                    } else if let Some(k) = keyboard_driver.keyboard_to_keypad(key) {
                         processor.press_key(k as usize, true);
                     }
                 }
                 Event::KeyUp{keycode: Some(key), ..} => {
-                    if let Some(k) = keyboard_driver.keyboard_to_keypad(key) {
+                    if keyboard_driver.is_rewind_key(key) {
+                        is_rewinding = false;
+                    } else if let Some(k) = keyboard_driver.keyboard_to_keypad(key) {
                         processor.press_key(k as usize, false);
                     }
                 },
+                // The window contents are lost when it is uncovered, and unchanged frames aren't redrawn
+                Event::Window { win_event: WindowEvent::Exposed | WindowEvent::SizeChanged(..), .. } => {
+                    graphics_driver.invalidate();
+                }
                 _ => {   }
             }
         }
 
-        if processor.sound_timer > 0 {
-            sound_driver.play_sound();
+        if let Some(sound_driver) = sound_driver.as_mut() {
+            sound_driver.set_pattern(processor.audio_pattern(), processor.audio_playback_rate());
+
+            if processor.sound_timer > 0 && !is_rewinding && fault.is_none() {
+                sound_driver.play_sound();
+            } else {
+                sound_driver.stop_sound();
+            }
+        }
+
+        if processor.has_exited() {
+            break 'gameloop;
+        }
+
+        if is_rewinding {
+            // Step back one frame for every frame the rewind key is held
+            if let Some(state) = rewind_buffer.pop() {
+                processor.load_state(&state)?;
+                restore_sound(&mut sound_driver, &processor);
+                // Keys held at the time of the snapshot would otherwise stay stuck down
+                processor.release_keys();
+                if fault.take().is_some() {
+                    window_title(&mut graphics_driver, &title)?;
+                }
+            }
+        } else if fault.is_some() {
+            // Keep showing the display as it was when the machine stopped
+        } else if let Some((debugger, repl)) = debugger.as_mut() {
+            repl.poll(debugger, &mut processor);
+            if !debugger.is_paused() {
+                rewind_buffer.push(processor.save_state());
+            }
+            if let Some(reason) = debugger.run_frame(&mut processor) {
+                Repl::report(&reason);
+            }
         } else {
-            sound_driver.stop_sound();
+            rewind_buffer.push(processor.save_state());
+            match processor.run_frame() {
+                Ok(()) => {}
+                Err(e @ (Chip8Error::UnknownOpcode { .. } | Chip8Error::MachineCodeRoutine { .. }))
+                    if processor.opcode_policy() == OpcodePolicy::Break => {
+                    // Open the debugger on the faulting instruction
+                    let mut fault_debugger: Debugger = Debugger::new();
+                    fault_debugger.pause(&processor);
+                    println!("Debugger enabled. Type help for a list of commands.");
+                    Repl::report(&BreakReason::Fault(e.to_string()));
+                    debugger = Some((fault_debugger, Repl::spawn()));
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    window_title(&mut graphics_driver, &format!("{} - {}", title, e))?;
+                    fault = Some(e);
+                }
+            }
         }
 
-        processor.tick();
-        graphics_driver.update_vram(processor.vram);
-        graphics_driver.draw_to_screen();
+        graphics_driver.update_vram(&processor.vram, processor.display_width());
+        graphics_driver.draw_to_screen()?;
+
+        // Pace the emulation to 60 frames a second using the host clock
+        next_frame += FRAME_DURATION;
+        let now: Instant = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            // Don't try to catch up if we fell behind, e.g. while the window was being dragged
+            next_frame = now;
+        }
     }
+
+    finish_trace(&mut processor)?;
+    finish_profile(&mut processor, &arguments)
+}
+
+/// Change the title of the window the canvas draws to
+fn window_title(graphics_driver: &mut GraphicsDriver, title: &str) -> Result<(), Chip8Error> {
+    graphics_driver.window_mut()
+        .set_title(title)
+        .map_err(|e| Chip8Error::Frontend(e.to_string()))
 }
@@ -0,0 +1,61 @@
+// Every test binary compiles this module but only uses some of the helpers
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use rusty_chip8::asm::assembler::Assembler;
+use rusty_chip8::chip8::cpu::CHIP8;
+use rusty_chip8::chip8::headless::Screenshot;
+use rusty_chip8::chip8::quirks::QuirkProfile;
+use rusty_chip8::chip8::rng::Chip8Rng;
+
+// Set to write the reference images from the current output instead of comparing against them
+const UPDATE_REFERENCES: &str = "UPDATE_REFERENCES";
+
+// Fixed seed so programs using Cxkk draw the same screen on every run
+const SEED: u64 = 0xC8;
+
+fn tests_directory() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Assemble a program written inline in a test
+pub fn assemble(source: &str) -> Vec<u8> {
+    Assembler::assemble_source(source, "test.asm").unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Read a ROM from tests/roms, if it has been downloaded
+pub fn read_rom(name: &str) -> Option<Vec<u8>> {
+    std::fs::read(tests_directory().join("roms").join(name)).ok()
+}
+
+/// Create a machine running a ROM with a quirk profile
+pub fn create_processor(profile: QuirkProfile, rom: &[u8]) -> CHIP8 {
+    let mut processor: CHIP8 = CHIP8::new(profile.platform(), profile.quirks());
+    processor.set_rng(Chip8Rng::new(SEED));
+    processor.load_rom_data(rom).unwrap();
+    processor
+}
+
+/// Compare a screenshot to the reference image tests/references/<name>.txt
+pub fn assert_screenshot(name: &str, screenshot: &Screenshot) {
+    let path: PathBuf = tests_directory().join("references").join(format!("{}.txt", name));
+    if std::env::var_os(UPDATE_REFERENCES).is_some() {
+        std::fs::write(&path, screenshot.to_string()).unwrap();
+        return;
+    }
+
+    let text: String = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!("No reference image at {}, run with {}=1 to create it\n{}", path.display(), UPDATE_REFERENCES, screenshot)
+    });
+    let reference: Screenshot = Screenshot::from_text(&text).unwrap();
+    let difference: usize = screenshot.difference(&reference);
+    assert!(
+        difference == 0,
+        "{} pixels differ from {}\nExpected:\n{}\nActual:\n{}",
+        difference,
+        path.display(),
+        reference,
+        screenshot
+    );
+}
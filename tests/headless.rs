@@ -0,0 +1,107 @@
+mod common;
+
+use rusty_chip8::chip8::headless::{HeadlessRunner, Screenshot};
+use rusty_chip8::chip8::quirks::QuirkProfile;
+use rusty_chip8::error::Chip8Error;
+
+// Draws the 16 digits of the font in two rows
+const FONT_DIGITS: &str = "
+        LD V0, 0
+        LD V1, 2
+        LD V2, 2
+draw:   LD F, V0
+        DRW V1, V2, 5
+        ADD V0, 1
+        ADD V1, 6
+        SE V0, 8
+        JP next
+        LD V1, 2
+        LD V2, 9
+next:   SE V0, 16
+        JP draw
+halt:   JP halt
+";
+
+// Waits for a key and draws its digit
+const WAIT_FOR_KEY: &str = "
+        LD V0, K
+        LD F, V0
+        LD V1, 10
+        LD V2, 10
+        DRW V1, V2, 5
+halt:   JP halt
+";
+
+// Draws a sprite on each bitplane, overlapping in the middle
+const BITPLANES: &str = "
+        PLANE 1
+        LD V0, 0
+        LD V1, 0
+        LD I, block
+        DRW V0, V1, 4
+        PLANE 2
+        LD V0, 2
+        DRW V0, V1, 4
+halt:   JP halt
+block:  db 0xF0, 0xF0, 0xF0, 0xF0
+";
+
+#[test]
+fn font_digits() {
+    let rom: Vec<u8> = common::assemble(FONT_DIGITS);
+    for profile in [QuirkProfile::CosmacVip, QuirkProfile::Schip11, QuirkProfile::XoChip, QuirkProfile::Modern] {
+        let mut runner: HeadlessRunner = HeadlessRunner::new(common::create_processor(profile, &rom));
+        runner.run(60).unwrap();
+        common::assert_screenshot("font_digits", &runner.screenshot());
+    }
+}
+
+#[test]
+fn scripted_key_press() {
+    let rom: Vec<u8> = common::assemble(WAIT_FOR_KEY);
+    let mut runner: HeadlessRunner = HeadlessRunner::new(common::create_processor(QuirkProfile::Modern, &rom));
+    runner.tap_key(10, 0xA, 2);
+
+    runner.run(10).unwrap();
+    assert_eq!(runner.screenshot().to_string().matches('#').count(), 0);
+
+    runner.run(20).unwrap();
+    assert_eq!(runner.processor().registers()[0], 0xA);
+    common::assert_screenshot("scripted_key_press", &runner.screenshot());
+}
+
+#[test]
+fn xochip_bitplanes() {
+    let rom: Vec<u8> = common::assemble(BITPLANES);
+    let mut runner: HeadlessRunner = HeadlessRunner::new(common::create_processor(QuirkProfile::XoChip, &rom));
+    runner.run(10).unwrap();
+    common::assert_screenshot("xochip_bitplanes", &runner.screenshot());
+}
+
+#[test]
+fn stops_when_the_program_exits() {
+    let rom: Vec<u8> = common::assemble("EXIT");
+    let mut runner: HeadlessRunner = HeadlessRunner::new(common::create_processor(QuirkProfile::Schip11, &rom));
+    runner.run(100).unwrap();
+    assert!(runner.processor().has_exited());
+    assert_eq!(runner.frame(), 1);
+}
+
+#[test]
+fn reports_faults() {
+    let rom: Vec<u8> = common::assemble("RET");
+    let mut runner: HeadlessRunner = HeadlessRunner::new(common::create_processor(QuirkProfile::Modern, &rom));
+    assert!(matches!(runner.run(10), Err(Chip8Error::StackUnderflow { pc: 0x200 })));
+    assert_eq!(runner.processor().pc(), 0x200);
+}
+
+#[test]
+fn screenshot_text_round_trip() {
+    let text: &str = "..#\n+@.\n";
+    let screenshot: Screenshot = Screenshot::from_text(text).unwrap();
+    assert_eq!((screenshot.width(), screenshot.height()), (3, 2));
+    assert_eq!(screenshot.to_string(), text);
+
+    assert!(Screenshot::from_text("..#\n.\n").is_err());
+    assert!(Screenshot::from_text("..x\n").is_err());
+}
@@ -0,0 +1,81 @@
+//! Runs the CHIP-8 test suite by Timendus, https://github.com/Timendus/chip8-test-suite
+//! The ROMs aren't distributed with the emulator. Download them into tests/roms and run
+//!     `cargo test --no-default-features -- --ignored`, using UPDATE_REFERENCES=1 to create
+//!     the reference images once their output has been checked by hand.
+
+mod common;
+
+use rusty_chip8::chip8::cpu::CHIP8;
+use rusty_chip8::chip8::headless::HeadlessRunner;
+use rusty_chip8::chip8::quirks::QuirkProfile;
+
+// The test ROMs read the test or platform to run from this address, which skips their menus
+const MENU_SELECTION: usize = 0x1FF;
+
+// Enough instructions that the tests don't report the interpreter as too slow
+const INSTRUCTIONS_PER_FRAME: u16 = 1000;
+
+/// Run a test ROM and compare its final screen to a reference image
+fn run_test_rom(file: &str, profile: QuirkProfile, menu_selection: Option<u8>, reference: &str) -> HeadlessRunner {
+    let rom: Vec<u8> = common::read_rom(file).unwrap_or_else(|| panic!("tests/roms/{} is missing", file));
+    let mut processor: CHIP8 = common::create_processor(profile, &rom);
+    processor.set_instructions_per_frame(INSTRUCTIONS_PER_FRAME);
+    if let Some(selection) = menu_selection {
+        processor.write_memory(MENU_SELECTION, &[selection]).unwrap();
+    }
+
+    let mut runner: HeadlessRunner = HeadlessRunner::new(processor);
+    runner.run(300).unwrap();
+    common::assert_screenshot(reference, &runner.screenshot());
+    runner
+}
+
+#[test]
+#[ignore = "needs 3-corax+.ch8 in tests/roms"]
+fn corax_plus() {
+    run_test_rom("3-corax+.ch8", QuirkProfile::CosmacVip, None, "timendus_corax_plus");
+}
+
+#[test]
+#[ignore = "needs 4-flags.ch8 in tests/roms"]
+fn flags() {
+    for (profile, reference) in [
+        (QuirkProfile::CosmacVip, "timendus_flags_cosmac"),
+        (QuirkProfile::Schip11, "timendus_flags_schip"),
+        (QuirkProfile::XoChip, "timendus_flags_xochip"),
+    ] {
+        run_test_rom("4-flags.ch8", profile, None, reference);
+    }
+}
+
+#[test]
+#[ignore = "needs 5-quirks.ch8 in tests/roms"]
+fn quirks_cosmac() {
+    run_test_rom("5-quirks.ch8", QuirkProfile::CosmacVip, Some(1), "timendus_quirks_cosmac");
+}
+
+#[test]
+#[ignore = "needs 5-quirks.ch8 in tests/roms"]
+fn quirks_schip() {
+    run_test_rom("5-quirks.ch8", QuirkProfile::Schip11, Some(2), "timendus_quirks_schip");
+}
+
+#[test]
+#[ignore = "needs 5-quirks.ch8 in tests/roms"]
+fn quirks_xochip() {
+    run_test_rom("5-quirks.ch8", QuirkProfile::XoChip, Some(3), "timendus_quirks_xochip");
+}
+
+#[test]
+#[ignore = "needs 6-keypad.ch8 in tests/roms"]
+fn keypad_wait_for_key() {
+    // The Fx0A test reports whether the key was picked up on press or on release
+    let rom: Vec<u8> = common::read_rom("6-keypad.ch8").expect("tests/roms/6-keypad.ch8 is missing");
+    let mut processor: CHIP8 = common::create_processor(QuirkProfile::CosmacVip, &rom);
+    processor.write_memory(MENU_SELECTION, &[3]).unwrap();
+
+    let mut runner: HeadlessRunner = HeadlessRunner::new(processor);
+    runner.tap_key(60, 0x5, 10);
+    runner.run(120).unwrap();
+    common::assert_screenshot("timendus_keypad_wait", &runner.screenshot());
+}